@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE block_syncs
+DROP COLUMN block_hash;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE block_syncs
+ADD COLUMN block_hash VARCHAR;
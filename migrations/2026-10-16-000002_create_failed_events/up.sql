@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE failed_events (
+  id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  transaction_hash VARCHAR NOT NULL,
+  event_sequence INTEGER NOT NULL,
+  block_height INTEGER NOT NULL,
+  block_timestamp TIMESTAMP NOT NULL,
+  contract_address VARCHAR NOT NULL,
+  initiator_address VARCHAR NOT NULL,
+  event_name VARCHAR NOT NULL,
+  params JSONB NOT NULL,
+  error TEXT NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX index_event_on_failed_events ON failed_events (transaction_hash, event_sequence);
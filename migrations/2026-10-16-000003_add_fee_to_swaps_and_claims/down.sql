@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE swaps DROP COLUMN fee;
+ALTER TABLE claims DROP COLUMN fee;
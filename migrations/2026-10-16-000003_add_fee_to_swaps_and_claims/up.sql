@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE swaps ADD COLUMN fee NUMERIC(39,0);
+ALTER TABLE claims ADD COLUMN fee NUMERIC(39,0);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE swaps DROP COLUMN success;
+ALTER TABLE liquidity_changes DROP COLUMN success;
+ALTER TABLE claims DROP COLUMN success;
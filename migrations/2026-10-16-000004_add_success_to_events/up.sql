@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE swaps ADD COLUMN success BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE liquidity_changes ADD COLUMN success BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE claims ADD COLUMN success BOOLEAN NOT NULL DEFAULT TRUE;
@@ -0,0 +1,15 @@
+-- Your SQL goes here
+CREATE TABLE reserves (
+  id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  transaction_hash VARCHAR NOT NULL,
+  event_sequence INTEGER NOT NULL,
+  block_height INTEGER NOT NULL,
+  block_timestamp TIMESTAMP NOT NULL,
+  token_address VARCHAR NOT NULL,
+  zil_reserve NUMERIC(39, 0) NOT NULL,
+  token_reserve NUMERIC(39, 0) NOT NULL
+);
+
+CREATE INDEX index_token_address_on_reserves ON reserves (token_address, block_height DESC, event_sequence DESC);
+CREATE INDEX index_block_height_on_reserves ON reserves (block_height);
+CREATE UNIQUE INDEX index_event_on_reserves ON reserves (transaction_hash, event_sequence);
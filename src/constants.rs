@@ -6,6 +6,7 @@ pub enum Event {
   Burnt,
   Swapped,
   Claimed,
+  Synced,
 }
 
 impl Event {
@@ -15,6 +16,7 @@ impl Event {
       "Burnt" => Some(Event::Burnt),
       "Swapped" => Some(Event::Swapped),
       "Claimed" => Some(Event::Claimed),
+      "PoolSynced" => Some(Event::Synced),
       _ => None,
     }
   }
@@ -27,6 +29,7 @@ impl fmt::Display for Event {
       Event::Burnt => write!(f, "Burnt"),
       Event::Swapped => write!(f, "Swapped"),
       Event::Claimed => write!(f, "Claimed"),
+      Event::Synced => write!(f, "PoolSynced"),
     }
   }
 }
@@ -35,6 +38,18 @@ impl fmt::Display for Event {
 pub enum Network {
   MainNet,
   TestNet,
+  LocalHost,
+}
+
+impl Network {
+  pub fn from_str(input: &str) -> Option<Network> {
+    match input {
+      "mainnet" => Some(Network::MainNet),
+      "testnet" => Some(Network::TestNet),
+      "localhost" => Some(Network::LocalHost),
+      _ => None,
+    }
+  }
 }
 
 impl fmt::Display for Network {
@@ -42,6 +57,7 @@ impl fmt::Display for Network {
     match *self {
       Network::MainNet => write!(f, "mainnet"),
       Network::TestNet => write!(f, "testnet"),
+      Network::LocalHost => write!(f, "localhost"),
     }
   }
 }
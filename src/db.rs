@@ -2,22 +2,108 @@ use diesel::debug_query;
 use diesel::pg::Pg;
 use diesel::prelude::*;
 use diesel::dsl::{sql, exists, max};
-use diesel::sql_types::{Text, Numeric, Timestamp};
+use diesel::expression::SqlLiteral;
+use diesel::sql_types::{Bool, Integer, Nullable, Text, Numeric, Timestamp};
+use bigdecimal::BigDecimal;
 use chrono::{NaiveDateTime, Utc};
 use redis::Commands;
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicUsize, Ordering};
+use uuid::Uuid;
 
 use crate::models;
 use crate::pagination::*;
 
-/// Get paginated swaps.
-pub fn get_swaps(
-  conn: &PgConnection,
-  per_page: Option<i64>,
-  page: Option<i64>,
-  pool: Option<&str>,
-  address: Option<&str>,
-  is_incoming: Option<&bool>,
-) -> Result<PaginatedResult<models::Swap>, diesel::result::Error> {
+/// An inclusive range of block heights to filter records by.
+#[derive(Clone, Copy, Default)]
+pub struct BlockRange {
+  pub from: Option<i32>,
+  pub to: Option<i32>,
+}
+
+/// Whether a liquidity change adds or removes liquidity, by the sign of its amount.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LiquidityChangeType {
+  Add,
+  Remove,
+}
+
+/// Fields that claims can be sorted by.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClaimSort {
+  EpochNumber,
+  BlockTimestamp,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+  Asc,
+  Desc,
+}
+
+/// Swaps to leave out of swap queries and volumes.
+#[derive(Clone, Copy, Default)]
+pub struct SwapExclusions {
+  /// Exclude suspected wash trades, see `not_wash_trade`.
+  pub wash_trades: bool,
+  /// Exclude swaps with a zero amount on both sides.
+  pub zero_amounts: bool,
+}
+
+static WASH_TRADE_WINDOW: AtomicI64 = AtomicI64::new(300);
+
+/// Sets the window in seconds used to detect wash trades. Should be called once at startup.
+pub fn configure_wash_trade_window(seconds: i64) {
+  WASH_TRADE_WINDOW.store(seconds.max(0), Ordering::Relaxed);
+}
+
+static CACHE_TTL: AtomicUsize = AtomicUsize::new(60);
+static HISTORICAL_CACHE_TTL: AtomicUsize = AtomicUsize::new(6 * 3600);
+static CACHE_DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Seconds that a request may hold the lock for computing a cache entry.
+const CACHE_LOCK_SECS: u64 = 30;
+
+/// Sets the seconds to cache results for periods that are still ongoing, and for
+/// periods that have ended. Should be called once at startup.
+pub fn configure_cache_ttl(seconds: usize, historical_seconds: usize) {
+  CACHE_TTL.store(seconds, Ordering::Relaxed);
+  HISTORICAL_CACHE_TTL.store(historical_seconds, Ordering::Relaxed);
+}
+
+/// Bypasses the cache for all queries, for debugging. Should be called once at startup.
+pub fn disable_cache() {
+  CACHE_DISABLED.store(true, Ordering::Relaxed);
+}
+
+/// Filters out suspected wash trades: swaps where the same initiator also swapped
+/// in the opposite direction on the same pool within the configured window, i.e.
+/// a round trip that inflates volume without changing the initiator's position.
+/// This is a heuristic and will also exclude some legitimate trades.
+fn not_wash_trade() -> SqlLiteral<Bool> {
+  sql::<Bool>(&format!(
+    "NOT EXISTS (SELECT 1 FROM swaps w \
+      WHERE w.initiator_address = swaps.initiator_address \
+      AND w.token_address = swaps.token_address \
+      AND w.is_sending_zil <> swaps.is_sending_zil \
+      AND ABS(EXTRACT(EPOCH FROM (w.block_timestamp - swaps.block_timestamp))) <= {})",
+    WASH_TRADE_WINDOW.load(Ordering::Relaxed),
+  ))
+}
+
+/// Build a swaps query with the given filters applied.
+fn filter_swaps<'a>(
+  pool: Option<&'a str>,
+  address: Option<&'a str>,
+  is_incoming: Option<&'a bool>,
+  is_success: Option<&'a bool>,
+  exclusions: SwapExclusions,
+  block_range: BlockRange,
+) -> crate::schema::swaps::BoxedQuery<'a, Pg> {
   // It is common when using Diesel with Actix web to import schema-related
   // modules inside a function's scope (rather than the normal module's scope)
   // to prevent import collisions and namespace pollution.
@@ -40,11 +126,85 @@ pub fn get_swaps(
     query = query.filter(is_sending_zil.eq(is_incoming))
   }
 
-  Ok(query
+  if let Some(is_success) = is_success {
+    query = query.filter(success.eq(is_success))
+  }
+
+  if exclusions.wash_trades {
+    query = query.filter(not_wash_trade())
+  }
+
+  if exclusions.zero_amounts {
+    query = query.filter(token_amount.ne(BigDecimal::default()).or(zil_amount.ne(BigDecimal::default())))
+  }
+
+  if let Some(from) = block_range.from {
+    query = query.filter(block_height.ge(from))
+  }
+
+  if let Some(to) = block_range.to {
+    query = query.filter(block_height.le(to))
+  }
+
+  query
+}
+
+/// Get paginated swaps, optionally only those up to the `as_of` timestamp
+/// so that pages stay the same while new swaps are indexed.
+#[allow(clippy::too_many_arguments)]
+pub fn get_swaps(
+  conn: &PgConnection,
+  per_page: Option<i64>,
+  page: Option<i64>,
+  pool: Option<&str>,
+  address: Option<&str>,
+  is_incoming: Option<&bool>,
+  is_success: Option<&bool>,
+  exclusions: SwapExclusions,
+  block_range: BlockRange,
+  as_of: Option<i64>,
+) -> Result<PaginatedResult<models::Swap>, diesel::result::Error> {
+  use crate::schema::swaps::dsl::*;
+
+  let mut query = filter_swaps(pool, address, is_incoming, is_success, exclusions, block_range);
+
+  if let Some(as_of) = as_of {
+    query = query.filter(block_timestamp.le(NaiveDateTime::from_timestamp(as_of, 0)))
+  }
+
+  query
     .order(block_timestamp.desc())
     .paginate(page)
     .per_page(per_page)
-    .load_and_count_pages::<models::Swap>(conn)?)
+    .load_and_count_pages::<models::Swap>(conn)
+}
+
+/// Get swaps after the given cursor, paginated by keyset.
+#[allow(clippy::too_many_arguments)]
+pub fn get_swaps_after(
+  conn: &PgConnection,
+  per_page: Option<i64>,
+  cursor: Option<Cursor>,
+  pool: Option<&str>,
+  address: Option<&str>,
+  is_incoming: Option<&bool>,
+  is_success: Option<&bool>,
+  exclusions: SwapExclusions,
+  block_range: BlockRange,
+  as_of: Option<i64>,
+) -> Result<CursorPaginatedResult<models::Swap>, diesel::result::Error> {
+  use crate::schema::swaps::dsl::*;
+
+  let mut query = filter_swaps(pool, address, is_incoming, is_success, exclusions, block_range);
+
+  if let Some(as_of) = as_of {
+    query = query.filter(block_timestamp.le(NaiveDateTime::from_timestamp(as_of, 0)))
+  }
+
+  query
+    .paginate_after(cursor)
+    .per_page(per_page)
+    .load_with_cursor::<models::Swap>(conn)
 }
 
 /// Get paginated liquidity changes.
@@ -54,6 +214,8 @@ pub fn get_liquidity_changes(
   page: Option<i64>,
   pool: Option<&str>,
   address: Option<&str>,
+  change_type: Option<LiquidityChangeType>,
+  block_range: BlockRange,
 ) -> Result<PaginatedResult<models::LiquidityChange>, diesel::result::Error> {
   use crate::schema::liquidity_changes::dsl::*;
 
@@ -67,21 +229,36 @@ pub fn get_liquidity_changes(
     query = query.filter(initiator_address.eq(address));
   }
 
-  Ok(query
+  match change_type {
+    Some(LiquidityChangeType::Add) => query = query.filter(change_amount.gt(BigDecimal::from(0))),
+    Some(LiquidityChangeType::Remove) => query = query.filter(change_amount.lt(BigDecimal::from(0))),
+    None => {},
+  }
+
+  if let Some(from) = block_range.from {
+    query = query.filter(block_height.ge(from));
+  }
+
+  if let Some(to) = block_range.to {
+    query = query.filter(block_height.le(to));
+  }
+
+  query
     .order(block_timestamp.desc())
     .paginate(page)
     .per_page(per_page)
-    .load_and_count_pages::<models::LiquidityChange>(conn)?
-  )
+    .load_and_count_pages::<models::LiquidityChange>(conn)
 }
 
-/// Get distributions by epoch, optionally filtered by address.
+/// Get paginated distributions by epoch in address order, optionally filtered by address.
 pub fn get_distributions(
   conn: &PgConnection,
   distr_address: Option<&str>,
   epoch: Option<i32>,
   address: Option<&str>,
-) -> Result<Vec<models::Distribution>, diesel::result::Error> {
+  per_page: Option<i64>,
+  page: Option<i64>,
+) -> Result<PaginatedResult<models::Distribution>, diesel::result::Error> {
   use crate::schema::distributions::dsl::*;
 
   let mut query = distributions.into_boxed::<Pg>();
@@ -98,10 +275,29 @@ pub fn get_distributions(
     query = query.filter(distributor_address.eq(distr_address));
   }
 
-  Ok(query
+  query
     .order(address_bech32.asc())
-    .load::<models::Distribution>(conn)?
-  )
+    .paginate(page)
+    .per_page(per_page)
+    .load_and_count_pages::<models::Distribution>(conn)
+}
+
+/// Get the merkle root of an epoch's distribution as hex, which is the last hash of any of its proofs.
+pub fn get_merkle_root(
+  conn: &PgConnection,
+  distr_address: &str,
+  epoch: i32,
+) -> Result<Option<String>, diesel::result::Error> {
+  use crate::schema::distributions::dsl::*;
+
+  let maybe_proof: Option<String> = distributions
+    .select(proof)
+    .filter(distributor_address.eq(distr_address))
+    .filter(epoch_number.eq(epoch))
+    .first(conn)
+    .optional()?;
+
+  Ok(maybe_proof.and_then(|p| p.split_whitespace().last().map(|root| root.to_owned())))
 }
 
 /// Get all distributions for an address.
@@ -115,7 +311,24 @@ pub fn get_distributions_by_address(
     .order(epoch_number.asc())
     .filter(address_bech32.eq(address));
 
-  Ok(query.load(conn)?)
+  query.load(conn)
+}
+
+/// Get a single distribution by distributor address, epoch number and address
+pub fn get_distribution(
+  conn: &PgConnection,
+  distr_address: &str,
+  epoch: i32,
+  address: &str,
+) -> Result<Option<models::Distribution>, diesel::result::Error> {
+  use crate::schema::distributions::dsl::*;
+
+  distributions
+    .filter(distributor_address.eq(distr_address))
+    .filter(epoch_number.eq(epoch))
+    .filter(address_bech32.eq(address))
+    .first(conn)
+    .optional()
 }
 
 /// Get a single claim by address, distributor address and epoch number
@@ -137,11 +350,17 @@ pub fn get_claim(
 }
 
 /// Get all claims, optionally filtered by address and/or distributor address
+#[allow(clippy::too_many_arguments)]
 pub fn get_claims(
   conn: &PgConnection,
   address: Option<&str>,
   distr_address: Option<&str>,
   epoch: Option<&i32>,
+  start_timestamp: Option<i64>,
+  end_timestamp: Option<i64>,
+  block_range: BlockRange,
+  sort: Option<ClaimSort>,
+  order: Option<SortOrder>,
   per_page: Option<i64>,
   page: Option<i64>,
 ) -> Result<PaginatedResult<models::Claim>, diesel::result::Error> {
@@ -161,12 +380,35 @@ pub fn get_claims(
     query = query.filter(epoch_number.eq(epoch));
   }
 
-  Ok(query
-    .order(epoch_number.asc())
+  // filter start time, inclusive
+  if let Some(start_timestamp) = start_timestamp {
+    query = query.filter(block_timestamp.ge(NaiveDateTime::from_timestamp(start_timestamp, 0)))
+  }
+
+  // filter end time, exclusive
+  if let Some(end_timestamp) = end_timestamp {
+    query = query.filter(block_timestamp.lt(NaiveDateTime::from_timestamp(end_timestamp, 0)))
+  }
+
+  if let Some(from) = block_range.from {
+    query = query.filter(block_height.ge(from));
+  }
+
+  if let Some(to) = block_range.to {
+    query = query.filter(block_height.le(to));
+  }
+
+  query = match (sort.unwrap_or(ClaimSort::EpochNumber), order.unwrap_or(SortOrder::Asc)) {
+    (ClaimSort::EpochNumber, SortOrder::Asc) => query.order(epoch_number.asc()),
+    (ClaimSort::EpochNumber, SortOrder::Desc) => query.order(epoch_number.desc()),
+    (ClaimSort::BlockTimestamp, SortOrder::Asc) => query.order(block_timestamp.asc()),
+    (ClaimSort::BlockTimestamp, SortOrder::Desc) => query.order(block_timestamp.desc()),
+  };
+
+  query
     .paginate(page)
     .per_page(per_page)
-    .load_and_count_pages::<models::Claim>(conn)?
-  )
+    .load_and_count_pages::<models::Claim>(conn)
 }
 
 /// Get unclaimed distributions for an address.
@@ -189,26 +431,94 @@ pub fn get_unclaimed_distributions_by_address(
   let query = diesel::sql_query(sql)
     .bind::<Text, _>(address);
 
-  Ok(query.load::<models::Distribution>(conn)?)
+  query.load::<models::Distribution>(conn)
 }
 
-/// Get all pools.
+/// Get the total amounts distributed, claimed and still claimable by an address for each distributor.
+pub fn get_distribution_summary_by_address(
+  conn: &PgConnection,
+  address: &str,
+) -> Result<Vec<models::DistributionSummary>, diesel::result::Error> {
+  let sql = "
+    SELECT d.distributor_address,
+    SUM(d.amount) AS distributed_amount,
+    SUM(CASE WHEN c.id IS NULL THEN 0 ELSE d.amount END) AS claimed_amount,
+    SUM(CASE WHEN c.id IS NULL THEN d.amount ELSE 0 END) AS claimable_amount
+    FROM distributions d
+    LEFT OUTER JOIN claims c
+    ON d.distributor_address = c.distributor_address
+    AND d.epoch_number = c.epoch_number
+    AND d.address_bech32 = c.initiator_address
+    AND c.success
+    WHERE address_bech32 = $1
+    GROUP BY d.distributor_address
+  ";
+
+  let query = diesel::sql_query(sql)
+    .bind::<Text, _>(address);
+
+  query.load::<models::DistributionSummary>(conn)
+}
+
+/// Get all pools that have either liquidity changes or swaps.
 pub fn get_pools(
   conn: &PgConnection,
 ) -> Result<Vec<String>, diesel::result::Error> {
-  use crate::schema::liquidity_changes::dsl::*;
+  use crate::schema::{liquidity_changes, swaps};
+
+  let liquidity_pools: BTreeSet<String> = liquidity_changes::table
+    .select(liquidity_changes::token_address)
+    .distinct()
+    .load::<String>(conn)?
+    .into_iter()
+    .collect();
+
+  let swap_pools: BTreeSet<String> = swaps::table
+    .select(swaps::token_address)
+    .distinct()
+    .load::<String>(conn)?
+    .into_iter()
+    .collect();
+
+  // a pool with swaps but no liquidity changes means mint / burn events were missed
+  for pool in swap_pools.difference(&liquidity_pools) {
+    warn!("Pool {} has swaps but no liquidity changes", pool);
+  }
 
-  let query = liquidity_changes
-    .select(token_address)
-    .distinct();
+  Ok(liquidity_pools.union(&swap_pools).cloned().collect())
+}
+
+/// Get the first and latest activity of a pool across its swaps and liquidity changes.
+pub fn get_pool_lifespan(
+  conn: &PgConnection,
+  pool: &str,
+) -> Result<Option<models::PoolLifespan>, diesel::result::Error> {
+  let sql = "
+    SELECT
+      $1 AS pool,
+      MIN(block_height) AS first_block_height,
+      MIN(block_timestamp) AS first_block_timestamp,
+      MAX(block_height) AS last_block_height,
+      MAX(block_timestamp) AS last_block_timestamp
+    FROM (
+      SELECT block_height, block_timestamp FROM swaps WHERE token_address = $1
+      UNION ALL
+      SELECT block_height, block_timestamp FROM liquidity_changes WHERE token_address = $1
+    ) events
+    HAVING COUNT(*) > 0
+  ";
 
-  Ok(query.load(conn)?)
+  let query = diesel::sql_query(sql)
+    .bind::<Text, _>(pool);
+
+  query.get_result::<models::PoolLifespan>(conn).optional()
 }
 
-/// Get liquidity at a point in time filtered optionally by address.
+/// Get liquidity at a point in time or block height filtered optionally by address.
 pub fn get_liquidity(
   conn: &PgConnection,
   timestamp: Option<i64>,
+  block: Option<i32>,
   address: Option<&str>,
 ) -> Result<Vec<models::Liquidity>, diesel::result::Error> {
   use crate::schema::liquidity_changes::dsl::*;
@@ -229,7 +539,125 @@ pub fn get_liquidity(
     query = query.filter(block_timestamp.le(NaiveDateTime::from_timestamp(timestamp, 0)))
   }
 
-  Ok(query.load::<models::Liquidity>(conn)?)
+  if let Some(block) = block {
+    query = query.filter(block_height.le(block))
+  }
+
+  query.load::<models::Liquidity>(conn)
+}
+
+/// Get the total liquidity added and removed in each pool over the given period, optionally for one pool.
+pub fn get_liquidity_flow(
+  conn: &PgConnection,
+  pool: Option<&str>,
+  start_timestamp: Option<i64>,
+  end_timestamp: Option<i64>,
+) -> Result<Vec<models::LiquidityFlow>, diesel::result::Error> {
+  use crate::schema::liquidity_changes::dsl::*;
+
+  let mut query = liquidity_changes
+    .group_by(token_address)
+    .select((
+      sql::<Text>("token_address AS pool"),
+      // mints have positive change amounts and burns negative ones
+      sql::<Numeric>("SUM(GREATEST(change_amount, 0)) AS added_amount"),
+      sql::<Numeric>("SUM(GREATEST(-change_amount, 0)) AS removed_amount"),
+    ))
+    .into_boxed::<Pg>();
+
+  if let Some(pool) = pool {
+    query = query.filter(token_address.eq(pool));
+  }
+
+  // filter start time, inclusive
+  if let Some(start_timestamp) = start_timestamp {
+    query = query.filter(block_timestamp.ge(NaiveDateTime::from_timestamp(start_timestamp, 0)))
+  }
+
+  // filter end time, exclusive
+  if let Some(end_timestamp) = end_timestamp {
+    query = query.filter(block_timestamp.lt(NaiveDateTime::from_timestamp(end_timestamp, 0)))
+  }
+
+  query.load::<models::LiquidityFlow>(conn)
+}
+
+/// Get the number of addresses with liquidity in each pool at a point in time, optionally for one pool.
+pub fn count_lps_per_pool(
+  conn: &PgConnection,
+  timestamp: Option<i64>,
+  pool: Option<&str>,
+) -> Result<Vec<models::LpCount>, diesel::result::Error> {
+  let sql = "
+    SELECT pool, COUNT(*) AS count
+    FROM (
+      SELECT token_address AS pool, initiator_address
+      FROM liquidity_changes
+      WHERE block_timestamp <= $1
+      AND ($2::TEXT IS NULL OR token_address = $2)
+      GROUP BY token_address, initiator_address
+      HAVING SUM(change_amount) > 0
+    ) balances
+    GROUP BY pool
+    ORDER BY pool
+  ";
+
+  let at = match timestamp {
+    Some(timestamp) => NaiveDateTime::from_timestamp(timestamp, 0),
+    None => Utc::now().naive_utc(),
+  };
+
+  let query = diesel::sql_query(sql)
+    .bind::<Timestamp, _>(at)
+    .bind::<Nullable<Text>, _>(pool);
+
+  query.load::<models::LpCount>(conn)
+}
+
+/// Get the share of a pool's liquidity held by an address, optionally at the given timestamp.
+pub fn get_pool_share(
+  conn: &PgConnection,
+  pool: &str,
+  address: &str,
+  timestamp: Option<i64>,
+) -> Result<models::PoolShare, diesel::result::Error> {
+  use crate::schema::liquidity_changes::dsl::*;
+
+  let mut query = liquidity_changes
+    .filter(token_address.eq(pool))
+    .into_boxed::<Pg>();
+
+  if let Some(timestamp) = timestamp {
+    query = query.filter(block_timestamp.le(NaiveDateTime::from_timestamp(timestamp, 0)))
+  }
+
+  let total: Option<BigDecimal> = query.select(diesel::dsl::sum(change_amount)).first(conn)?;
+
+  let mut query = liquidity_changes
+    .filter(token_address.eq(pool))
+    .filter(initiator_address.eq(address))
+    .into_boxed::<Pg>();
+
+  if let Some(timestamp) = timestamp {
+    query = query.filter(block_timestamp.le(NaiveDateTime::from_timestamp(timestamp, 0)))
+  }
+
+  let amount: Option<BigDecimal> = query.select(diesel::dsl::sum(change_amount)).first(conn)?;
+
+  Ok(models::PoolShare::new(pool, address, amount.unwrap_or_default(), total.unwrap_or_default()))
+}
+
+/// Get the most recent swap for each pool.
+pub fn get_last_swaps(
+  conn: &PgConnection,
+) -> Result<Vec<models::Swap>, diesel::result::Error> {
+  use crate::schema::swaps::dsl::*;
+
+  let query = swaps
+    .distinct_on(token_address)
+    .order((token_address, block_timestamp.desc(), event_sequence.desc()));
+
+  query.load::<models::Swap>(conn)
 }
 
 /// Gets the swap volume for all pools over the given period in zil / token amounts.
@@ -238,6 +666,7 @@ pub fn get_volume(
   address: Option<&str>,
   start_timestamp: Option<i64>,
   end_timestamp: Option<i64>,
+  exclusions: SwapExclusions,
 ) -> Result<Vec<models::Volume>, diesel::result::Error> {
   use crate::schema::swaps::dsl::*;
 
@@ -267,13 +696,23 @@ pub fn get_volume(
       query = query.filter(block_timestamp.lt(NaiveDateTime::from_timestamp(end_timestamp, 0)))
     }
 
-    Ok(query.load::<models::Volume>(conn)?)
+    if exclusions.wash_trades {
+      query = query.filter(not_wash_trade())
+    }
+
+    if exclusions.zero_amounts {
+      query = query.filter(token_amount.ne(BigDecimal::default()).or(zil_amount.ne(BigDecimal::default())))
+    }
+
+    query.load::<models::Volume>(conn)
 }
 
 
-/// Gets the swap volume for all pools over the given period in zil amounts by address.
+/// Gets the swap volume for all pools over the given period in zil amounts by address,
+/// filtered optionally by address.
 pub fn get_volume_by_address(
   conn: &PgConnection,
+  address: Option<&str>,
   start_timestamp: Option<i64>,
   end_timestamp: Option<i64>,
 ) -> Result<Vec<models::VolumeForUser>, diesel::result::Error> {
@@ -288,6 +727,10 @@ pub fn get_volume_by_address(
     ))
     .into_boxed::<Pg>();
 
+    if let Some(address) = address {
+      query = query.filter(initiator_address.eq(address));
+    }
+
     // filter start time, inclusive
     if let Some(start_timestamp) = start_timestamp {
       query = query.filter(block_timestamp.ge(NaiveDateTime::from_timestamp(start_timestamp, 0)))
@@ -298,10 +741,72 @@ pub fn get_volume_by_address(
       query = query.filter(block_timestamp.lt(NaiveDateTime::from_timestamp(end_timestamp, 0)))
     }
 
-    Ok(query.load::<models::VolumeForUser>(conn)?)
+    query.load::<models::VolumeForUser>(conn)
+}
+
+/// Prefix of the keys that time-weighted liquidity is cached under. Each entry holds
+/// the liquidity of one pool, and is indexed by the end of its period under
+/// `{prefix}:{pool}` so that a liquidity change invalidates only that pool's entries.
+fn liquidity_cache_prefix() -> String {
+  let network = std::env::var("NETWORK").unwrap_or(String::from("testnet"));
+  format!("zap-api-cache:{}:get_time_weighted_liquidity", network)
+}
+
+/// Reads cached time-weighted liquidity of the given pools, treating cache errors
+/// or any missing pool as a miss.
+fn get_cached_liquidity(cache: &mut redis::Connection, pools: &[String], cache_keys: &[String]) -> Option<Vec<models::Liquidity>> {
+  let cache_values: Vec<Option<String>> = redis::cmd("MGET").arg(cache_keys).query(cache).ok()?;
+  let mut result = vec![];
+  for (pool, cache_value) in pools.iter().zip(cache_values) {
+    // pools without liquidity in the period are cached as null
+    if let Some(amount) = serde_json::from_str::<Option<BigDecimal>>(&cache_value?).ok()? {
+      result.push(models::Liquidity { pool: pool.clone(), amount });
+    }
+  }
+  Some(result)
+}
+
+/// Caches time-weighted liquidity of the given pools and adds each entry to its
+/// pool's index, scored by the end of the period.
+fn cache_liquidity(
+  cache: &mut redis::Connection,
+  pools: &[String],
+  cache_keys: &[String],
+  result: &[models::Liquidity],
+  end_timestamp: Option<i64>,
+  cache_ttl: usize,
+) {
+  // indexes outlive the entries in them, which are deleted when they are invalidated
+  let index_ttl = std::cmp::max(CACHE_TTL.load(Ordering::Relaxed), HISTORICAL_CACHE_TTL.load(Ordering::Relaxed));
+  let score = end_timestamp.map(|end_timestamp| end_timestamp.to_string()).unwrap_or(String::from("+inf"));
+  let prefix = liquidity_cache_prefix();
+
+  let mut pipe = redis::pipe();
+  for (pool, cache_key) in pools.iter().zip(cache_keys) {
+    let amount = result.iter().find(|l| &l.pool == pool).map(|l| &l.amount);
+    let cache_value: String = serde_json::to_string(&amount).expect("failed to serialize result to cache");
+    let index_key = format!("{}:{}", prefix, pool);
+    pipe.set_ex(cache_key, cache_value, cache_ttl).ignore()
+      .zadd(&index_key, cache_key, &score).ignore()
+      .expire(&index_key, index_ttl).ignore();
+  }
+  if let Err(e) = pipe.query::<()>(cache) {
+    error!("{}", e)
+  }
+}
+
+/// Takes the lock for computing a cache entry, which expires after `CACHE_LOCK_SECS`
+/// in case its holder fails. If the cache is unavailable the lock is taken, as
+/// there is nothing to wait for.
+fn lock_cache_key(cache: &mut redis::Connection, lock_key: &str) -> bool {
+  redis::cmd("SET").arg(lock_key).arg(1).arg("NX").arg("EX").arg(CACHE_LOCK_SECS)
+    .query::<Option<String>>(cache)
+    .map(|r| r.is_some())
+    .unwrap_or(true)
 }
 
 /// Get time-weighted liquidity for all pools over a period filtered optionally by address.
+/// Results are cached per pool unless the cache is disabled.
 pub fn get_time_weighted_liquidity(
   conn: &PgConnection,
   cache: &mut redis::Connection,
@@ -309,12 +814,88 @@ pub fn get_time_weighted_liquidity(
   end_timestamp: Option<i64>,
   address: Option<&str>,
 ) -> Result<Vec<models::Liquidity>, diesel::result::Error> {
-  let address_fragment = match address {
-    Some(_addr) => "AND initiator_address = $3", // bind later
-    None => "AND '1' = $3", // bind to noop
+  if CACHE_DISABLED.load(Ordering::Relaxed) {
+    return get_uncached_time_weighted_liquidity(conn, start_timestamp, end_timestamp, address)
+  }
+
+  // results for a period that has ended do not change, so they can be cached for longer
+  let cache_ttl = match end_timestamp {
+    Some(end_timestamp) if NaiveDateTime::from_timestamp(end_timestamp, 0) < Utc::now().naive_utc() => HISTORICAL_CACHE_TTL.load(Ordering::Relaxed),
+    _ => CACHE_TTL.load(Ordering::Relaxed),
   };
-  let noop = "1";
 
+  let pools = get_pools(conn)?;
+  if pools.is_empty() {
+    return Ok(vec![])
+  }
+
+  let prefix = liquidity_cache_prefix();
+  let suffix = format!("{}:{}:{}", start_timestamp.unwrap_or(0).to_string(), end_timestamp.unwrap_or(0).to_string(), address.unwrap_or(""));
+  let cache_keys: Vec<String> = pools.iter().map(|pool| format!("{}:{}:{}", prefix, pool, suffix)).collect();
+  if let Some(result) = get_cached_liquidity(cache, &pools, &cache_keys) {
+    return Ok(result)
+  }
+
+  // only one request computes missing entries, others wait for them to be cached
+  let lock_key = format!("{}:{}:lock", prefix, suffix);
+  let locked = lock_cache_key(cache, &lock_key);
+  if !locked {
+    for _ in 0..(CACHE_LOCK_SECS * 10) {
+      std::thread::sleep(std::time::Duration::from_millis(100));
+      if let Some(result) = get_cached_liquidity(cache, &pools, &cache_keys) {
+        return Ok(result)
+      }
+    }
+    warn!("Timed out waiting for {} to be cached, computing it instead.", suffix);
+  }
+
+  let result = get_uncached_time_weighted_liquidity(conn, start_timestamp, end_timestamp, address)?;
+
+  cache_liquidity(cache, &pools, &cache_keys, &result, end_timestamp, cache_ttl);
+  if locked {
+    if let Err(e) = cache.del::<String, ()>(lock_key) {
+      error!("{}", e)
+    }
+  }
+
+  Ok(result)
+}
+
+/// Deletes cached time-weighted liquidity of a pool that a liquidity change by the
+/// given address at the given time affects: that of periods which end after it,
+/// either for all addresses or for that address. Returns the number of entries deleted.
+pub fn invalidate_time_weighted_liquidity(
+  cache: &mut redis::Connection,
+  pool: &str,
+  address: &str,
+  timestamp: NaiveDateTime,
+) -> redis::RedisResult<usize> {
+  let index_key = format!("{}:{}", liquidity_cache_prefix(), pool);
+  let keys: Vec<String> = cache.zrangebyscore(&index_key, format!("({}", timestamp.timestamp()), "+inf")?;
+
+  // keys are suffixed with the address they are filtered by, or nothing
+  let address_suffix = format!(":{}", address);
+  let affected: Vec<String> = keys.into_iter()
+    .filter(|key| key.ends_with(':') || key.ends_with(&address_suffix))
+    .collect();
+
+  if affected.is_empty() {
+    return Ok(0)
+  }
+  redis::pipe()
+    .del(&affected).ignore()
+    .zrem(&index_key, &affected).ignore()
+    .query::<()>(cache)?;
+  Ok(affected.len())
+}
+
+/// Get time-weighted liquidity like `get_time_weighted_liquidity`, bypassing the cache.
+pub fn get_uncached_time_weighted_liquidity(
+  conn: &PgConnection,
+  start_timestamp: Option<i64>,
+  end_timestamp: Option<i64>,
+  address: Option<&str>,
+) -> Result<Vec<models::Liquidity>, diesel::result::Error> {
   let start_dt = match start_timestamp {
     Some(start_timestamp) => NaiveDateTime::from_timestamp(start_timestamp, 0),
     None => NaiveDateTime::from_timestamp(0, 0),
@@ -325,19 +906,6 @@ pub fn get_time_weighted_liquidity(
     None => Utc::now().naive_utc(),
   };
 
-  let network = std::env::var("NETWORK").unwrap_or(String::from("testnet"));
-  let cache_key = format!("zap-api-cache:{}:get_time_weighted_liquidity:{}:{}:{}", network, start_timestamp.unwrap_or(0).to_string(), end_timestamp.unwrap_or(0).to_string(), address.unwrap_or(""));
-  let cache_value: Option<String> = cache.get(cache_key.clone()).unwrap_or(None);
-  match cache_value {
-    Some (serialized) => {
-      match serde_json::from_str::<Vec<models::Liquidity>>(&serialized) {
-        Ok(result) => return Ok(result),
-        _ => {}
-      }
-    }
-    _ => {}
-  }
-
   // local test query
   // "WITH t AS (
   //   SELECT
@@ -367,7 +935,7 @@ pub fn get_time_weighted_liquidity(
   // )
   // GROUP BY token_address;"
 
-  let sql = format!("
+  let sql = "
     WITH t AS (
       SELECT
         token_address,
@@ -378,7 +946,7 @@ pub fn get_time_weighted_liquidity(
         SUM(change_amount) OVER (PARTITION BY token_address ORDER BY block_timestamp ASC, transaction_hash ASC ROWS BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW) AS current
       FROM liquidity_changes
       WHERE block_timestamp < $2
-      {}
+      AND ($3::TEXT IS NULL OR initiator_address = $3)
       WINDOW w AS (PARTITION BY token_address ORDER BY block_timestamp ASC)
     ),
     data AS (
@@ -398,23 +966,16 @@ pub fn get_time_weighted_liquidity(
       (token_address, row_number) IN (SELECT token_address, MAX(row_number) FROM data WHERE start_timestamp < $1 GROUP BY token_address)
     )
     GROUP BY token_address;
-  ", address_fragment);
+  ";
 
   let query = diesel::sql_query(sql)
     .bind::<Timestamp, _>(start_dt)
     .bind::<Timestamp, _>(end_dt)
-    .bind::<Text, _>(address.unwrap_or(&noop));
+    .bind::<Nullable<Text>, _>(address);
 
   trace!("{}", debug_query(&query).to_string());
 
-  let result = query.load::<models::Liquidity>(conn)?;
-
-  let cache_value: String = serde_json::to_string(&result).expect("failed to serialize result to cache");
-  let _ = cache.set_ex::<String, String, ()>(cache_key, cache_value, 60).unwrap_or_else(|e| { // 1min cache
-    error!("{}", e)
-  });
-
-  Ok(result)
+  query.load::<models::Liquidity>(conn)
 }
 
 /// Get time-weighted liquidity for all pools over a period grouped by address.
@@ -474,16 +1035,18 @@ pub fn get_time_weighted_liquidity_by_address(
 
   trace!("{}", debug_query(&query).to_string());
 
-  Ok(query.load::<models::LiquidityFromProvider>(conn)?)
+  query.load::<models::LiquidityFromProvider>(conn)
 }
 
 /// List LP transactions
+#[allow(clippy::too_many_arguments)]
 pub fn get_transactions(
   conn: &PgConnection,
   address: Option<&str>,
   pool: Option<&str>,
   start_timestamp: Option<i64>,
   end_timestamp: Option<i64>,
+  block_range: BlockRange,
   per_page: Option<i64>,
   page: Option<i64>,
 ) -> Result<PaginatedResult<models::PoolTx>, diesel::result::Error> {
@@ -512,11 +1075,19 @@ pub fn get_transactions(
     query = query.filter(block_timestamp.lt(NaiveDateTime::from_timestamp(end_timestamp, 0)))
   }
 
-  Ok(query
+  if let Some(from) = block_range.from {
+    query = query.filter(block_height.ge(from))
+  }
+
+  if let Some(to) = block_range.to {
+    query = query.filter(block_height.le(to))
+  }
+
+  query
     .order(block_timestamp.desc())
     .paginate(page)
     .per_page(per_page)
-    .load_and_count_pages::<models::PoolTx>(conn)?)
+    .load_and_count_pages::<models::PoolTx>(conn)
 }
 
 /// Get the liquidity over time of all pools
@@ -559,34 +1130,65 @@ pub fn get_transactions(
 //   ORDER BY token_address ASC, start_timestamp ASC;
 // ";
 
-/// Inserts a new swap into the db.
+/// Inserts a new swap into the db, skipping it if the event was already inserted.
+/// Returns whether the swap was inserted.
 pub fn insert_swap(
   new_swap: models::NewSwap,
   conn: &PgConnection,
-) -> Result<(), diesel::result::Error> {
+) -> Result<bool, diesel::result::Error> {
   use crate::schema::swaps::dsl::*;
 
-  diesel::insert_into(swaps)
+  let count = diesel::insert_into(swaps)
     .values(&new_swap)
-    .on_conflict_do_nothing()
+    .on_conflict((transaction_hash, event_sequence))
+    .do_nothing()
     .execute(conn)?;
 
-  Ok(())
+  Ok(count > 0)
 }
 
-/// Inserts a new liquidity change into the db.
+/// Inserts a new liquidity change into the db, skipping it if the event was already inserted.
+/// Returns whether the liquidity change was inserted.
 pub fn insert_liquidity_change(
   new_liquidity_change: models::NewLiquidityChange,
   conn: &PgConnection,
-) -> Result<(), diesel::result::Error> {
+) -> Result<bool, diesel::result::Error> {
   use crate::schema::liquidity_changes::dsl::*;
 
-  diesel::insert_into(liquidity_changes)
+  let count = diesel::insert_into(liquidity_changes)
     .values(&new_liquidity_change)
-    .on_conflict_do_nothing()
+    .on_conflict((transaction_hash, event_sequence))
+    .do_nothing()
     .execute(conn)?;
 
-  Ok(())
+  Ok(count > 0)
+}
+
+pub fn insert_reserve(
+  new_reserve: models::NewReserve,
+  conn: &PgConnection,
+) -> Result<bool, diesel::result::Error> {
+  use crate::schema::reserves::dsl::*;
+
+  let count = diesel::insert_into(reserves)
+    .values(&new_reserve)
+    .on_conflict((transaction_hash, event_sequence))
+    .do_nothing()
+    .execute(conn)?;
+
+  Ok(count > 0)
+}
+
+/// Get the latest indexed reserves of each pool.
+pub fn get_latest_reserves(
+  conn: &PgConnection,
+) -> Result<Vec<models::Reserve>, diesel::result::Error> {
+  use crate::schema::reserves::dsl::*;
+
+  reserves
+    .distinct_on(token_address)
+    .order((token_address, block_height.desc(), event_sequence.desc()))
+    .load::<models::Reserve>(conn)
 }
 
 /// Inserts multiple distributions into the db.
@@ -603,35 +1205,138 @@ pub fn insert_distributions(
   Ok(())
 }
 
-/// Inserts a new claim into the db.
+/// Inserts a new claim into the db, skipping it if the claim was already inserted.
+/// Returns whether the claim was inserted.
 pub fn insert_claim(
   new_claim: models::NewClaim,
   conn: &PgConnection,
-) -> Result<(), diesel::result::Error> {
+) -> Result<bool, diesel::result::Error> {
   use crate::schema::claims::dsl::*;
 
-  diesel::insert_into(claims)
+  let count = diesel::insert_into(claims)
     .values(&new_claim)
-    .on_conflict_do_nothing()
+    .on_conflict((initiator_address, distributor_address, epoch_number))
+    .do_nothing()
     .execute(conn)?;
 
-  Ok(())
+  Ok(count > 0)
 }
 
+/// Marks a block as synced, doing nothing if it already is.
 pub fn insert_block_sync(
   conn: &PgConnection,
   new_block_sync: models::NewBlockSync,
 ) -> Result<(), diesel::result::Error> {
   use crate::schema::block_syncs::dsl::*;
 
-  let _res = diesel::insert_into(block_syncs)
+  diesel::insert_into(block_syncs)
     .values(&new_block_sync)
-    .on_conflict_do_nothing()
-    .execute(conn);
+    .on_conflict(block_height)
+    .do_nothing()
+    .execute(conn)?;
 
   Ok(())
 }
 
+/// Records an event that could not be persisted, replacing the error if it was already recorded.
+pub fn insert_failed_event(
+  conn: &PgConnection,
+  new_failed_event: models::NewFailedEvent,
+) -> Result<(), diesel::result::Error> {
+  use crate::schema::failed_events::dsl::*;
+
+  diesel::insert_into(failed_events)
+    .values(&new_failed_event)
+    .on_conflict((transaction_hash, event_sequence))
+    .do_update()
+    .set(error.eq(new_failed_event.error))
+    .execute(conn)?;
+
+  Ok(())
+}
+
+/// Get all failed events, most recent first.
+pub fn get_failed_events(
+  conn: &PgConnection,
+  per_page: Option<i64>,
+  page: Option<i64>,
+) -> Result<PaginatedResult<models::FailedEvent>, diesel::result::Error> {
+  use crate::schema::failed_events::dsl::*;
+
+  failed_events
+    .order(created_at.desc())
+    .paginate(page)
+    .per_page(per_page)
+    .load_and_count_pages::<models::FailedEvent>(conn)
+}
+
+pub fn get_failed_event(
+  conn: &PgConnection,
+  failed_event_id: Uuid,
+) -> Result<Option<models::FailedEvent>, diesel::result::Error> {
+  use crate::schema::failed_events::dsl::*;
+
+  failed_events
+    .find(failed_event_id)
+    .first(conn)
+    .optional()
+}
+
+pub fn delete_failed_event(
+  conn: &PgConnection,
+  failed_event_id: Uuid,
+) -> Result<(), diesel::result::Error> {
+  use crate::schema::failed_events::dsl::*;
+
+  diesel::delete(failed_events.find(failed_event_id)).execute(conn)?;
+  Ok(())
+}
+
+/// Get the block sync for the given height, if the block has been synced.
+pub fn get_block_sync(
+  conn: &PgConnection,
+  height: i32,
+) -> Result<Option<models::BlockSync>, diesel::result::Error> {
+  use crate::schema::block_syncs::dsl::*;
+
+  block_syncs
+    .filter(block_height.eq(height))
+    .first(conn)
+    .optional()
+}
+
+/// Get the block syncs in an inclusive range of heights, ordered by height.
+pub fn get_block_syncs(
+  conn: &PgConnection,
+  from: i32,
+  to: i32,
+) -> Result<Vec<models::BlockSync>, diesel::result::Error> {
+  use crate::schema::block_syncs::dsl::*;
+
+  block_syncs
+    .filter(block_height.between(from, to))
+    .order(block_height)
+    .load(conn)
+}
+
+/// Deletes all events and the block sync for the given height so that the block can be reprocessed.
+pub fn rollback_block(
+  conn: &PgConnection,
+  height: i32,
+) -> Result<Vec<(String, String, NaiveDateTime)>, diesel::result::Error> {
+  use crate::schema::{block_syncs, claims, failed_events, liquidity_changes, reserves, swaps};
+
+  diesel::delete(swaps::table.filter(swaps::block_height.eq(height))).execute(conn)?;
+  let liquidity_changes = diesel::delete(liquidity_changes::table.filter(liquidity_changes::block_height.eq(height)))
+    .returning((liquidity_changes::token_address, liquidity_changes::initiator_address, liquidity_changes::block_timestamp))
+    .get_results(conn)?;
+  diesel::delete(claims::table.filter(claims::block_height.eq(height))).execute(conn)?;
+  diesel::delete(reserves::table.filter(reserves::block_height.eq(height))).execute(conn)?;
+  diesel::delete(failed_events::table.filter(failed_events::block_height.eq(height))).execute(conn)?;
+  diesel::delete(block_syncs::table.filter(block_syncs::block_height.eq(height))).execute(conn)?;
+
+  Ok(liquidity_changes)
+}
 
 pub fn swap_exists(
   conn: &PgConnection,
@@ -639,8 +1344,8 @@ pub fn swap_exists(
 ) -> Result<bool, diesel::result::Error> {
   use crate::schema::swaps::dsl::*;
 
-  Ok(diesel::select(exists(swaps.filter(transaction_hash.eq(hash))))
-    .get_result(conn)?)
+  diesel::select(exists(swaps.filter(transaction_hash.eq(hash))))
+    .get_result(conn)
 }
 
 pub fn liquidity_change_exists(
@@ -648,8 +1353,8 @@ pub fn liquidity_change_exists(
   hash: &str,
 ) -> Result<bool, diesel::result::Error> {
   use crate::schema::liquidity_changes::dsl::*;
-  Ok(diesel::select(exists(liquidity_changes.filter(transaction_hash.eq(hash))))
-    .get_result(conn)?)
+  diesel::select(exists(liquidity_changes.filter(transaction_hash.eq(hash))))
+    .get_result(conn)
 }
 
 pub fn epoch_exists(
@@ -659,18 +1364,334 @@ pub fn epoch_exists(
 ) -> Result<bool, diesel::result::Error> {
   use crate::schema::distributions::dsl::*;
 
-  Ok(diesel::select(exists(distributions.filter(epoch_number.eq(epoch)).filter(distributor_address.eq(distr_address))))
-    .get_result(conn)?)
+  diesel::select(exists(distributions.filter(epoch_number.eq(epoch)).filter(distributor_address.eq(distr_address))))
+    .get_result(conn)
+}
+
+/// Get the heights between `from` and `to` (inclusive) that have no block sync.
+pub fn find_missing_block_heights(
+  conn: &PgConnection,
+  from: i32,
+  to: i32,
+) -> Result<Vec<i32>, diesel::result::Error> {
+  let sql = "
+    SELECT h.block_height
+    FROM generate_series($1, $2) AS h(block_height)
+    LEFT OUTER JOIN block_syncs b
+    ON b.block_height = h.block_height
+    WHERE b.id IS NULL
+    ORDER BY h.block_height
+  ";
+
+  let query = diesel::sql_query(sql)
+    .bind::<Integer, _>(from)
+    .bind::<Integer, _>(to);
+
+  let heights = query.load::<models::BlockHeight>(conn)?;
+  Ok(heights.into_iter().map(|h| h.block_height).collect())
 }
 
 pub fn last_sync_height(
   conn: &PgConnection,
 ) -> Result<i32, diesel::result::Error> {
   use crate::schema::block_syncs::dsl::*;
-  let result = block_syncs.select(max(block_height)).first(conn)?;
-  let last_height = match result {
-    Some(height) => height,
-    None => 0,
-  };
-  Ok(last_height) 
+  let result: Option<i32> = block_syncs.select(max(block_height)).first(conn)?;
+  Ok(result.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use bigdecimal::BigDecimal;
+  use crate::test_utils::*;
+  use std::str::FromStr;
+
+  /// The block heights of the records on a page, in order.
+  fn block_heights(page: impl serde::Serialize) -> Vec<i64> {
+    let page = serde_json::to_value(page).unwrap();
+    page["records"].as_array().unwrap().iter().map(|record| record["block_height"].as_i64().unwrap()).collect()
+  }
+
+  #[test]
+  #[ignore]
+  fn test_volume_by_address_across_pools() {
+    let conn = test_conn();
+    let (pool_a, pool_b) = (random_address(), random_address());
+    let (address, other) = (random_address(), random_address());
+    seed_swap(&conn, &pool_a, &address, 1, 100, 10, true);
+    seed_swap(&conn, &pool_a, &address, 2, 50, 5, false);
+    seed_swap(&conn, &pool_b, &address, 3, 70, 7, true);
+    seed_swap(&conn, &pool_a, &other, 4, 1000, 100, true);
+
+    let mut volumes = get_volume_by_address(&conn, Some(&address), None, None).unwrap();
+    volumes.sort_by(|a, b| a.pool.cmp(&b.pool));
+    let mut expected = vec![
+      models::VolumeForUser { pool: pool_a, address: address.clone(), amount: BigDecimal::from(150) },
+      models::VolumeForUser { pool: pool_b, address: address.clone(), amount: BigDecimal::from(70) },
+    ];
+    expected.sort_by(|a, b| a.pool.cmp(&b.pool));
+    assert_eq!(volumes, expected);
+  }
+
+  #[test]
+  #[ignore]
+  fn test_pools_include_swaps_only_pools() {
+    let conn = test_conn();
+    let (liquidity_pool, swaps_only_pool) = (random_address(), random_address());
+    let address = random_address();
+    seed_liquidity_change(&conn, &liquidity_pool, &address, 1, 1000);
+    seed_swap(&conn, &swaps_only_pool, &address, 2, 100, 10, true);
+
+    let pools = get_pools(&conn).unwrap();
+    assert!(pools.contains(&liquidity_pool));
+    assert!(pools.contains(&swaps_only_pool));
+  }
+
+  #[test]
+  #[ignore]
+  fn test_pool_lifespan() {
+    let conn = test_conn();
+    let pool = random_address();
+    let address = random_address();
+    assert!(get_pool_lifespan(&conn, &pool).unwrap().is_none());
+
+    seed_swap(&conn, &pool, &address, 20, 100, 10, true);
+    seed_liquidity_change(&conn, &pool, &address, 10, 1000);
+    seed_liquidity_change(&conn, &pool, &address, 30, -1000);
+    seed_swap(&conn, &random_address(), &address, 40, 100, 10, true);
+
+    let lifespan = get_pool_lifespan(&conn, &pool).unwrap().unwrap();
+    assert_eq!(lifespan.pool, pool);
+    assert_eq!(lifespan.first_block_height, 10);
+    assert_eq!(lifespan.first_block_timestamp, block_timestamp(10));
+    assert_eq!(lifespan.last_block_height, 30);
+    assert_eq!(lifespan.last_block_timestamp, block_timestamp(30));
+  }
+
+  #[test]
+  #[ignore]
+  fn test_volume_denominated_in_zil() {
+    let conn = test_conn();
+    let pool = random_address();
+    let address = random_address();
+    seed_swap(&conn, &pool, &address, 1, 300, 20, true);
+    seed_swap(&conn, &pool, &address, 2, 150, 10, false);
+    seed_swap(&conn, &pool, &address, 3, 50, 4, true);
+
+    let volumes = get_volume(&conn, Some(&address), None, None, SwapExclusions::default()).unwrap();
+    assert_eq!(volumes.len(), 1);
+    assert_eq!(volumes[0].zil_amount(), BigDecimal::from(500));
+  }
+
+  #[test]
+  #[ignore]
+  fn test_wash_trades_excluded_under_flag() {
+    let conn = test_conn();
+    let pool = random_address();
+    let (wash_trader, trader) = (random_address(), random_address());
+    // a round trip 30s apart, within the default window of 300s
+    seed_swap(&conn, &pool, &wash_trader, 1, 100, 10, true);
+    seed_swap(&conn, &pool, &wash_trader, 2, 100, 10, false);
+    seed_swap(&conn, &pool, &trader, 3, 70, 7, true);
+
+    let initiators = |exclusions: SwapExclusions| {
+      let mut initiators: Vec<String> = filter_swaps(Some(&pool), None, None, None, exclusions, BlockRange::default())
+        .load::<models::Swap>(&conn)
+        .unwrap()
+        .into_iter()
+        .map(|swap| swap.initiator_address)
+        .collect();
+      initiators.sort();
+      initiators
+    };
+    let mut all = vec![wash_trader.clone(), wash_trader.clone(), trader.clone()];
+    all.sort();
+    assert_eq!(initiators(SwapExclusions::default()), all);
+    assert_eq!(initiators(SwapExclusions { wash_trades: true, ..SwapExclusions::default() }), vec![trader.clone()]);
+
+    let volume = |exclusions: SwapExclusions| {
+      get_volume(&conn, None, None, None, exclusions).unwrap().into_iter().find(|v| v.pool == pool).unwrap().zil_amount()
+    };
+    assert_eq!(volume(SwapExclusions::default()), BigDecimal::from(270));
+    assert_eq!(volume(SwapExclusions { wash_trades: true, ..SwapExclusions::default() }), BigDecimal::from(70));
+  }
+
+  #[test]
+  #[ignore]
+  fn test_block_range() {
+    let conn = test_conn();
+    let (distributor, pool, address) = (random_address(), random_address(), random_address());
+    for block_height in 1..=4 {
+      seed_claim(&conn, &distributor, &address, block_height, block_height, 100);
+      seed_swap(&conn, &pool, &address, block_height, 100, 10, true);
+    }
+    let block_range = BlockRange { from: Some(2), to: Some(3) };
+
+    let claims = get_claims(&conn, Some(&address), Some(&distributor), None, None, None, block_range, None, None, None, None).unwrap();
+    assert_eq!(block_heights(claims), vec![2, 3]);
+
+    let swaps = get_swaps(&conn, None, None, Some(&pool), None, None, None, SwapExclusions::default(), block_range, None).unwrap();
+    assert_eq!(block_heights(swaps), vec![3, 2]);
+  }
+
+  #[test]
+  #[ignore]
+  fn test_pool_share() {
+    let conn = test_conn();
+    let pool = random_address();
+    let (address, other) = (random_address(), random_address());
+    seed_liquidity_change(&conn, &pool, &address, 1, 1000);
+    seed_liquidity_change(&conn, &pool, &other, 2, 3000);
+
+    let share = get_pool_share(&conn, &pool, &address, None).unwrap();
+    assert_eq!(share.amount, BigDecimal::from(1000));
+    assert_eq!(share.total_amount, BigDecimal::from(4000));
+    assert_eq!(share.share, BigDecimal::from_str("0.25").unwrap());
+
+    let share = get_pool_share(&conn, &pool, &address, Some(block_timestamp(1).timestamp())).unwrap();
+    assert_eq!(share.share, BigDecimal::from(1));
+
+    seed_liquidity_change(&conn, &pool, &other, 3, -2000);
+    let share = get_pool_share(&conn, &pool, &address, None).unwrap();
+    assert_eq!(share.share, BigDecimal::from_str("0.5").unwrap());
+
+    let share = get_pool_share(&conn, &random_address(), &address, None).unwrap();
+    assert_eq!(share.share, BigDecimal::from(0));
+  }
+
+  #[test]
+  #[ignore]
+  fn test_zero_amounts_excluded_under_flag() {
+    let conn = test_conn();
+    let pool = random_address();
+    let address = random_address();
+    seed_swap(&conn, &pool, &address, 1, 0, 0, true);
+    seed_swap(&conn, &pool, &address, 2, 100, 0, true);
+    seed_swap(&conn, &pool, &address, 3, 70, 7, true);
+
+    // only the swap without an amount on either side is excluded
+    let count = |exclusions: SwapExclusions| {
+      filter_swaps(Some(&pool), None, None, None, exclusions, BlockRange::default()).load::<models::Swap>(&conn).unwrap().len()
+    };
+    let exclude_zero_amounts = SwapExclusions { zero_amounts: true, ..SwapExclusions::default() };
+    assert_eq!(count(SwapExclusions::default()), 3);
+    assert_eq!(count(exclude_zero_amounts), 2);
+
+    let volume = |exclusions: SwapExclusions| {
+      get_volume(&conn, None, None, None, exclusions).unwrap().into_iter().find(|v| v.pool == pool).unwrap().zil_amount()
+    };
+    assert_eq!(volume(SwapExclusions::default()), BigDecimal::from(170));
+    assert_eq!(volume(exclude_zero_amounts), BigDecimal::from(170));
+  }
+
+  #[test]
+  #[ignore]
+  fn test_lp_counts_exclude_withdrawn_addresses() {
+    let conn = test_conn();
+    let pool = random_address();
+    let (lp, withdrawn) = (random_address(), random_address());
+    seed_liquidity_change(&conn, &pool, &lp, 1, 1000);
+    seed_liquidity_change(&conn, &pool, &withdrawn, 2, 500);
+    seed_liquidity_change(&conn, &pool, &withdrawn, 3, -500);
+
+    let lp_count = |timestamp: Option<i64>| {
+      count_lps_per_pool(&conn, timestamp, Some(&pool)).unwrap().into_iter().map(|lp_count| lp_count.count).collect::<Vec<i64>>()
+    };
+    assert_eq!(lp_count(None), vec![1]);
+    assert_eq!(lp_count(Some(block_timestamp(2).timestamp())), vec![2]);
+  }
+
+  #[test]
+  #[ignore]
+  fn test_liquidity_flow() {
+    let conn = test_conn();
+    let pool = random_address();
+    let (address, other) = (random_address(), random_address());
+    seed_liquidity_change(&conn, &pool, &address, 1, 1000);
+    seed_liquidity_change(&conn, &pool, &other, 2, 500);
+    seed_liquidity_change(&conn, &pool, &address, 3, -300);
+    seed_liquidity_change(&conn, &pool, &other, 4, -200);
+
+    let flow = |start_timestamp: Option<i64>| {
+      let flows = get_liquidity_flow(&conn, Some(&pool), start_timestamp, None).unwrap();
+      assert_eq!(flows.len(), 1);
+      (flows[0].added_amount.clone(), flows[0].removed_amount.clone())
+    };
+    assert_eq!(flow(None), (BigDecimal::from(1500), BigDecimal::from(500)));
+    assert_eq!(flow(Some(block_timestamp(3).timestamp())), (BigDecimal::from(0), BigDecimal::from(500)));
+  }
+
+  #[test]
+  #[ignore]
+  fn test_time_weighted_liquidity() {
+    let conn = test_conn();
+    let pool = random_address();
+    let (address, other) = (random_address(), random_address());
+    // blocks are 30s apart, so the second change is an hour after the first
+    seed_liquidity_change(&conn, &pool, &address, 0, 1000);
+    seed_liquidity_change(&conn, &pool, &other, 120, 1000);
+    let start_timestamp = block_timestamp(0).timestamp() - 1;
+    let end_timestamp = block_timestamp(240).timestamp();
+
+    let liquidity = |address: Option<&str>| {
+      get_uncached_time_weighted_liquidity(&conn, Some(start_timestamp), Some(end_timestamp), address)
+        .unwrap()
+        .into_iter()
+        .find(|liquidity| liquidity.pool == pool)
+        .map(|liquidity| liquidity.amount)
+    };
+    // 1000 for an hour then 2000 for an hour
+    assert_eq!(liquidity(None), Some(BigDecimal::from(3000)));
+    assert_eq!(liquidity(Some(&address)), Some(BigDecimal::from(2000)));
+    assert_eq!(liquidity(Some(&other)), Some(BigDecimal::from(1000)));
+    assert_eq!(liquidity(Some(&random_address())), None);
+  }
+
+  #[test]
+  #[ignore]
+  fn test_swaps_as_of() {
+    let conn = test_conn();
+    let pool = random_address();
+    let address = random_address();
+    for block_height in 1..=4 {
+      seed_swap(&conn, &pool, &address, block_height, 100, 10, true);
+    }
+    let as_of = block_timestamp(4).timestamp();
+
+    let second_page = |as_of: Option<i64>| {
+      block_heights(get_swaps(&conn, Some(2), Some(2), Some(&pool), None, None, None, SwapExclusions::default(), BlockRange::default(), as_of).unwrap())
+    };
+    assert_eq!(second_page(Some(as_of)), vec![2, 1]);
+
+    // a swap indexed while paginating only shifts pages that are not pinned
+    seed_swap(&conn, &pool, &address, 5, 100, 10, true);
+    assert_eq!(second_page(Some(as_of)), vec![2, 1]);
+    assert_eq!(second_page(None), vec![3, 2]);
+  }
+
+  #[test]
+  #[ignore]
+  fn test_volume_fields() {
+    let conn = test_conn();
+    let pool = random_address();
+    let address = random_address();
+    seed_swap(&conn, &pool, &address, 1, 300, 20, true);
+    seed_swap(&conn, &pool, &address, 2, 150, 10, false);
+
+    let volumes = get_volume(&conn, Some(&address), None, None, SwapExclusions::default()).unwrap();
+    assert_eq!(volumes, vec![models::Volume {
+      pool: pool.clone(),
+      in_zil_amount: BigDecimal::from(300),
+      out_token_amount: BigDecimal::from(20),
+      out_zil_amount: BigDecimal::from(150),
+      in_token_amount: BigDecimal::from(10),
+    }]);
+    assert_eq!(serde_json::to_value(&volumes[0]).unwrap(), serde_json::json!({
+      "pool": pool,
+      "in_zil_amount": "300",
+      "out_token_amount": "20",
+      "out_zil_amount": "150",
+      "in_token_amount": "10",
+    }));
+  }
 }
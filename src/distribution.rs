@@ -1,19 +1,36 @@
 use bech32::{decode, FromBase32};
-use bigdecimal::{BigDecimal, Zero};
-use hex::{encode};
+use bigdecimal::{BigDecimal, Signed, Zero};
+use hex::{encode, decode as decode_hex};
 use ring::{digest};
 use serde::{Serialize, Deserialize};
+use num_bigint::Sign;
 use std::collections::HashMap;
 use std::convert::{TryInto};
+use std::fmt;
+use std::sync::atomic::{AtomicI64, Ordering};
 use std::time::{SystemTime};
 use std::str::{FromStr};
+use tiny_keccak::{Hasher as _, Keccak};
 use trees::{Tree, TreeWalk, Node, walk::Visit};
 
+use crate::utils;
+
 #[derive(Debug, Clone)]
 pub struct InvalidConfigError {
   details: String
 }
 
+#[derive(Debug, Clone)]
+pub struct InvalidAmountError {
+  details: String
+}
+
+impl fmt::Display for InvalidAmountError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.details)
+  }
+}
+
 pub trait Validate {
   fn validate(&self) -> Result<(), InvalidConfigError>;
 }
@@ -31,9 +48,23 @@ pub struct EmissionConfig {
   trader_token_ratio_bps: u16,
 }
 
+/// Shortest allowed epoch period in seconds.
+static MIN_EPOCH_PERIOD: AtomicI64 = AtomicI64::new(3600);
+
+/// Sets the shortest allowed epoch period in seconds, which must be positive.
+/// Should be called once at startup, before distribution configs are validated.
+pub fn configure_min_epoch_period(seconds: i64) {
+  MIN_EPOCH_PERIOD.store(seconds.max(1), Ordering::Relaxed);
+}
+
 impl Validate for EmissionConfig {
   fn validate(&self) -> Result<(), InvalidConfigError> {
     let mut errs = vec![];
+    let min_epoch_period = MIN_EPOCH_PERIOD.load(Ordering::Relaxed);
+    let min_epoch_period_err = format!("epoch_period must be at least {} seconds", min_epoch_period);
+    if self.epoch_period < min_epoch_period {
+      errs.push(min_epoch_period_err.as_str())
+    }
     if self.retroactive_distribution_cutoff_time > 0 && self.initial_epoch_number < 1 {
       errs.push("initial_epoch_number must be more than 0")
     }
@@ -64,6 +95,15 @@ impl Validate for EmissionConfig {
   }
 }
 
+/// The address receiving the developer share of a distribution, or
+/// a split of the developer share between addresses in bps.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum DeveloperAddress {
+  Single(String),
+  Split(HashMap<String, u16>),
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct DistributionConfig {
   name: String,
@@ -71,9 +111,13 @@ pub struct DistributionConfig {
   reward_token_address_hex: String,
   distributor_name: String,
   distributor_address_hex: String,
-  developer_address: String,
+  developer_address: DeveloperAddress,
   emission_info: EmissionConfig,
   incentivized_pools: HashMap<String, u32>,
+  #[serde(default)]
+  hash_scheme: HashScheme,
+  #[serde(default)]
+  redirect_addresses: HashMap<String, String>,
 }
 
 impl DistributionConfig {
@@ -85,8 +129,27 @@ impl DistributionConfig {
     self.name.as_str()
   }
 
-  pub fn developer_address(&self) -> &str {
-    self.developer_address.as_str()
+  /// Splits the given developer tokens between the developer addresses.
+  /// Split shares are not rounded, which is left to the caller.
+  pub fn developer_shares(&self, tokens: &BigDecimal) -> Vec<(String, BigDecimal)> {
+    match &self.developer_address {
+      DeveloperAddress::Single(address) => vec![(address.clone(), tokens.clone())],
+      DeveloperAddress::Split(split) => split.iter().map(|(address, bps)| {
+        (address.clone(), tokens * BigDecimal::from(*bps) / BigDecimal::from(10000))
+      }).collect(),
+    }
+  }
+
+  /// The developer address given what is left of an epoch after rounding down each share:
+  /// the single developer address, or the one with the largest split (the first by address on ties).
+  pub fn remainder_address(&self) -> String {
+    match &self.developer_address {
+      DeveloperAddress::Single(address) => address.clone(),
+      DeveloperAddress::Split(split) => split.iter()
+        .max_by(|(a1, bps1), (a2, bps2)| bps1.cmp(bps2).then(a2.cmp(a1)))
+        .map(|(address, _)| address.clone())
+        .expect("developer_address split is empty"),
+    }
   }
 
   pub fn distributor_address(&self) -> &str {
@@ -96,16 +159,51 @@ impl DistributionConfig {
   pub fn incentivized_pools(&self) -> HashMap<String, u32> {
     self.incentivized_pools.clone()
   }
+
+  pub fn hasher(&self) -> &'static dyn Hasher {
+    self.hash_scheme.hasher()
+  }
+
+  /// Addresses whose rewards are given to another address instead (from => to).
+  pub fn redirect_addresses(&self) -> &HashMap<String, String> {
+    &self.redirect_addresses
+  }
+}
+
+impl Validate for DistributionConfig {
+  fn validate(&self) -> Result<(), InvalidConfigError> {
+    let mut errs = vec![];
+    if let DeveloperAddress::Split(split) = &self.developer_address {
+      if split.keys().any(|address| decode(address).is_err()) {
+        errs.push(String::from("developer_address has an invalid address"))
+      }
+      let total_bps: u32 = split.values().map(|bps| *bps as u32).sum();
+      if total_bps != 10000 {
+        errs.push(format!("developer_address split must total 10000 bps, got {}", total_bps))
+      }
+    }
+    for (from, to) in self.redirect_addresses.iter() {
+      if decode(from).is_err() || decode(to).is_err() {
+        errs.push(format!("redirect from {} to {} has an invalid address", from, to))
+      } else if from == to {
+        errs.push(format!("redirect from {} is to itself", from))
+      } else if self.redirect_addresses.contains_key(to) {
+        errs.push(format!("redirect from {} is to {} which is itself redirected", from, to))
+      }
+    }
+    if errs.len() > 0 {
+      Err(InvalidConfigError{details: errs.join("\n")})
+    } else {
+      Ok(())
+    }
+  }
 }
 
 pub type DistributionConfigs = Vec<DistributionConfig>;
 impl Validate for DistributionConfigs {
   fn validate(&self) -> Result<(), InvalidConfigError> {
-    if self.len() == 0 {
-      return Err(InvalidConfigError{details: "No distributions found".to_owned()})
-    }
     for d in self {
-      if let Err(e) = d.emission_info.validate() {
+      if let Err(e) = d.emission_info.validate().and_then(|_| d.validate()) {
         return Err(InvalidConfigError{details: format!("Distribution for '{}' is invalid: {:?}", d.name, e)})
       }
     }
@@ -240,6 +338,55 @@ impl EpochInfo {
   }
 }
 
+/// The amounts of an epoch by address. Amounts are rounded down to whole
+/// tokens as they are added, keeping the dust left over from rounding.
+#[derive(Default)]
+pub struct EpochAmounts {
+  amounts: HashMap<String, BigDecimal>,
+  dust: BigDecimal,
+}
+
+impl EpochAmounts {
+  /// Adds tokens to the amount of an address, rounded down.
+  pub fn add(&mut self, address: String, tokens: BigDecimal) {
+    let rounded = utils::round_down(tokens.clone(), 0);
+    self.dust += tokens - rounded.clone();
+    let current = self.amounts.entry(address).or_default();
+    *current += rounded
+  }
+
+  /// Moves the amount of an address to another address, returning the amount moved.
+  pub fn redirect(&mut self, from: &str, to: &str) -> Option<BigDecimal> {
+    let amount = self.amounts.remove(from)?;
+    let current = self.amounts.entry(to.to_owned()).or_default();
+    *current += amount.clone();
+    Some(amount)
+  }
+
+  /// Gives the dust from rounding down each amount to an address, without going over
+  /// the given total. The dust is rounded to the nearest token, as it is a whole number
+  /// of tokens up to the precision of division. Returns the tokens given.
+  pub fn add_dust(&mut self, address: String, max_total: &BigDecimal) -> BigDecimal {
+    let dust = utils::round_down(self.dust.clone() + BigDecimal::new(5.into(), 1), 0);
+    let dust = std::cmp::min(dust, max_total - self.total());
+    self.dust = BigDecimal::default();
+    if !dust.is_positive() {
+      return BigDecimal::default()
+    }
+    let current = self.amounts.entry(address).or_default();
+    *current += dust.clone();
+    dust
+  }
+
+  pub fn total(&self) -> BigDecimal {
+    self.amounts.values().sum()
+  }
+
+  pub fn into_amounts(self) -> HashMap<String, BigDecimal> {
+    self.amounts
+  }
+}
+
 #[derive(Serialize, Clone)]
 pub struct Distribution {
   address: Vec::<u8>,
@@ -250,21 +397,31 @@ pub struct Distribution {
 }
 
 impl Distribution {
-  pub fn new(address: String, amount: BigDecimal) -> Distribution {
+  /// Creates a distribution leaf. The amount must be an integer that fits in
+  /// the uint128 of the distributor contract.
+  pub fn new(address: String, amount: BigDecimal, hasher: &dyn Hasher) -> Result<Distribution, InvalidAmountError> {
+    let (big, exp) = amount.as_bigint_and_exponent();
+    if exp != 0 {
+      return Err(InvalidAmountError{details: format!("Amount {} for {} is not an integer", amount, address)})
+    }
+    if big.sign() == Sign::Minus || big.bits() > 128 {
+      return Err(InvalidAmountError{details: format!("Amount {} for {} does not fit in a uint128", amount, address)})
+    }
+
     let (_hrp, data) = decode(address.as_str()).expect("Could not decode bech32 string!");
     let bytes = Vec::<u8>::from_base32(&data).unwrap();
-    let hash = hash(&bytes, &amount);
+    let hash = hasher.hash_leaf(&bytes, &amount);
     let hex = encode(&bytes);
-    Distribution{address_human: address, address_hex: hex, address: bytes, amount, hash}
+    Ok(Distribution{address_human: address, address_hex: hex, address: bytes, amount, hash})
   }
 
-  pub fn from(map: HashMap<String, BigDecimal>) -> Vec<Distribution> {
+  pub fn from(map: HashMap<String, BigDecimal>, hasher: &dyn Hasher) -> Result<Vec<Distribution>, InvalidAmountError> {
     let mut arr: Vec<Distribution> = vec![];
     for (k, v) in map.into_iter() {
-      let d = Distribution::new(k, v);
+      let d = Distribution::new(k, v, hasher)?;
       arr.push(d);
     }
-    arr
+    Ok(arr)
   }
 
   pub fn address_bech32(&self) -> &str {
@@ -284,47 +441,96 @@ impl Distribution {
   }
 }
 
-fn hash(address: &Vec::<u8>, amount: &BigDecimal) -> Vec<u8> {
-  // convert the amount to big-endian bytes
-  let (big, exp) = amount.as_bigint_and_exponent();
-  if exp != 0 {
-    panic!("Non-integer distribution amount received!");
+/// The hash function used for the leaves and nodes of a distribution's merkle tree,
+/// which must match the one used by the distributor contract.
+pub trait Hasher: Sync {
+  fn digest(&self, data: &[u8]) -> Vec<u8>;
+
+  /// Hashes a leaf: the amount as 16 big-endian bytes is hashed, then
+  /// the 20 address bytes are prepended to that and hashed again.
+  fn hash_leaf(&self, address: &[u8], amount: &BigDecimal) -> Vec<u8> {
+    // convert the amount to big-endian bytes
+    let (big, exp) = amount.as_bigint_and_exponent();
+    if exp != 0 {
+      panic!("Non-integer distribution amount received!");
+    }
+    let (_sign, bytes) = big.to_bytes_be();
+    let zeroes = vec![0; 16 - bytes.len()];
+    let amount_bytes = [zeroes, bytes].concat();
+    trace!("amount_bytes: {:?}", amount_bytes);
+
+    // hash the amount bytes
+    let amount_hash = self.digest(&amount_bytes);
+    trace!("digest: {:?}", amount_hash);
+
+    // concat 20 address bytes to the 32 bytes amount hash
+    let value_to_hash = [address.to_vec(), amount_hash].concat();
+
+    // debug: hash the concatted value
+    let final_hash = self.digest(&value_to_hash);
+    trace!("value to hash: {}", encode(&value_to_hash));
+    trace!("final hash: {}", encode(&final_hash));
+
+    final_hash
+  }
+}
+
+pub struct Sha256Hasher;
+
+impl Hasher for Sha256Hasher {
+  fn digest(&self, data: &[u8]) -> Vec<u8> {
+    digest::digest(&digest::SHA256, data).as_ref().to_vec()
   }
-  let (_sign, bytes) = big.to_bytes_be();
-  let zeroes = vec![0; 16 - bytes.len()];
-  let amount_bytes = [zeroes, bytes].concat();
-  trace!("amount_bytes: {:?}", amount_bytes);
+}
 
-  // hash the amount bytes
-  let digest = digest::digest(&digest::SHA256, &amount_bytes);
-  trace!("digest: {:?}", digest);
-  let amount_hash = digest.as_ref();
+pub struct Keccak256Hasher;
 
-  // concat 20 address bytes to the 32 bytes amount hash
-  let value_to_hash = [address.to_vec(), amount_hash.to_vec()].concat();
+impl Hasher for Keccak256Hasher {
+  fn digest(&self, data: &[u8]) -> Vec<u8> {
+    let mut keccak = Keccak::v256();
+    let mut output = [0u8; 32];
+    keccak.update(data);
+    keccak.finalize(&mut output);
+    output.to_vec()
+  }
+}
 
-  // debug: hash the concatted value
-  let final_hash = digest::digest(&digest::SHA256, &value_to_hash);
-  trace!("value to hash: {}", encode(value_to_hash.to_vec()));
-  trace!("final hash: {}", encode(final_hash.as_ref().to_vec()));
+/// Selects the `Hasher` of a distribution in the config file.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum HashScheme {
+  #[default]
+  Sha256,
+  Keccak256,
+}
 
-  final_hash.as_ref().to_vec()
+impl HashScheme {
+  pub fn hasher(&self) -> &'static dyn Hasher {
+    match self {
+      HashScheme::Sha256 => &Sha256Hasher,
+      HashScheme::Keccak256 => &Keccak256Hasher,
+    }
+  }
 }
 
 type Data = (Option<Distribution>, Vec<u8>);
 type MerkleTree = Tree<Data>;
 
-pub fn construct_merkle_tree(data: Vec<Distribution>) -> MerkleTree {
+pub fn construct_merkle_tree(data: Vec<Distribution>, hasher: &dyn Hasher) -> MerkleTree {
   trace!("Build tree:");
   let mut leaves: Vec<MerkleTree> = vec![];
   for d in data.into_iter() {
     let hash = d.hash.clone();
     leaves.push(MerkleTree::new((Some(d), hash)));
   }
-  build_parents(leaves)
+  build_parents(leaves, hasher)
 }
 
-fn build_parents(mut input: Vec<MerkleTree>) -> MerkleTree {
+/// Builds the tree up from the given nodes. Each level is sorted by hash and paired in
+/// order, so the tree only depends on the set of leaves. If a level has an odd number
+/// of nodes, the last one is carried up to the next level unchanged, and proofs of its
+/// leaves have no sibling for that level.
+fn build_parents(mut input: Vec<MerkleTree>, hasher: &dyn Hasher) -> MerkleTree {
   trace!("Build parents:");
   input.sort_by_key(|c| c.data().1.clone()); // sort by hash
   let mut children = std::collections::VecDeque::from(input);
@@ -338,9 +544,9 @@ fn build_parents(mut input: Vec<MerkleTree>) -> MerkleTree {
           Some(c2) => {
             trace!("Joining:\n{:?}\n{:?}", encode(c1.data().1.clone()), encode(c2.data().1.clone()));
             let concat = [c1.data().1.clone(), c2.data().1.clone()].concat();
-            let hash = digest::digest(&digest::SHA256, &concat);
-            trace!("Hash:\n{:?}", encode(hash.as_ref().to_vec()));
-            let mut parent = MerkleTree::new((None, hash.as_ref().to_vec()));
+            let hash = hasher.digest(&concat);
+            trace!("Hash:\n{:?}", encode(hash.clone()));
+            let mut parent = MerkleTree::new((None, hash));
             parent.push_back(c1);
             parent.push_back(c2);
             nodes.push(parent);
@@ -355,13 +561,17 @@ fn build_parents(mut input: Vec<MerkleTree>) -> MerkleTree {
         if nodes.len() == 1 {
           return nodes[0].clone()
         }
-        return build_parents(nodes)
+        return build_parents(nodes, hasher)
       }
     }
   }
 }
 
 pub fn get_proofs(tree: MerkleTree) -> Vec<(Distribution, String)> {
+  // the walk does not visit a lone root as a leaf
+  if tree.root().has_no_child() {
+    return vec![(tree.root().data().0.clone().unwrap(), get_proof(tree.root()))]
+  }
   let mut res: Vec<(Distribution, String)> = vec![];
   let mut walk = TreeWalk::from(tree);
   loop {
@@ -374,6 +584,8 @@ pub fn get_proofs(tree: MerkleTree) -> Vec<(Distribution, String)> {
   }
 }
 
+/// Gets the proof of a leaf: its hash, the hashes of its siblings from the leaf upwards
+/// and the root hash. Levels where a node was carried up unpaired add no sibling.
 fn get_proof(leaf: &Node<Data>) -> String {
   let mut res = String::new();
   let mut needle = leaf;
@@ -399,3 +611,147 @@ fn get_proof(leaf: &Node<Data>) -> String {
   }
   res
 }
+
+/// Verifies a proof as built by `get_proof` (the leaf hash, the sibling hashes
+/// from the leaf upwards and the root hash, space-separated) for the given leaf
+/// against the given merkle root.
+pub fn verify_proof(leaf: &Distribution, proof: &str, root: &[u8], hasher: &dyn Hasher) -> bool {
+  let hashes: Option<Vec<Vec<u8>>> = proof.split_whitespace().map(|h| decode_hex(h).ok()).collect();
+  let hashes = match hashes {
+    Some(hashes) if hashes.len() >= 2 => hashes,
+    _ => return false,
+  };
+  if hashes[0] != leaf.hash {
+    return false
+  }
+
+  // siblings are joined in the order of their hashes, as in `build_parents`
+  let (proof_root, siblings) = hashes[1..].split_last().unwrap();
+  let computed_root = siblings.iter().fold(leaf.hash.clone(), |hash, sibling| {
+    let concat = if hash <= *sibling {
+      [hash, sibling.clone()].concat()
+    } else {
+      [sibling.clone(), hash].concat()
+    };
+    hasher.digest(&concat)
+  });
+  computed_root == *proof_root && computed_root == root
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // zil1p5suryq6q647usxczale29cu3336hhp376c627 in hex
+  const ADDRESS: &str = "0d21c1901a06abee40d8177f95171c8c63abdc31";
+
+  fn emission(epoch_period: i64) -> EmissionConfig {
+    EmissionConfig {
+      epoch_period,
+      tokens_per_epoch: String::from("1000"),
+      tokens_for_retroactive_distribution: String::from("0"),
+      retroactive_distribution_cutoff_time: 0,
+      distribution_start_time: 1_600_000_000,
+      total_number_of_epochs: 10,
+      initial_epoch_number: 0,
+      developer_token_ratio_bps: 1500,
+      trader_token_ratio_bps: 2000,
+    }
+  }
+
+  fn leaf_hash(hasher: &dyn Hasher, amount: i64) -> String {
+    encode(hasher.hash_leaf(&decode_hex(ADDRESS).unwrap(), &BigDecimal::from(amount)))
+  }
+
+  #[test]
+  fn test_sha256_golden_vectors() {
+    let hasher = HashScheme::Sha256.hasher();
+    assert_eq!(encode(hasher.digest(b"abc")), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    assert_eq!(leaf_hash(hasher, 1_000_000), "c5831f8875bc44497f2e16e56af099f165fd95a7e32ed01b159e4deac4ca12c6");
+    assert_eq!(leaf_hash(hasher, 6_250_000_000_000_000), "e722409e083cad3c19c2d2236027c08b76d589e47dad121dfabb600e92ba6401");
+  }
+
+  #[test]
+  fn test_keccak256_golden_vectors() {
+    let hasher = HashScheme::Keccak256.hasher();
+    assert_eq!(encode(hasher.digest(b"")), "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470");
+    assert_eq!(encode(hasher.digest(b"abc")), "4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45");
+    assert_eq!(leaf_hash(hasher, 1_000_000), "10aeefe52e754a83a57500054c4942611ffaab3113c81085d69a049f91ffdac4");
+    assert_eq!(leaf_hash(hasher, 6_250_000_000_000_000), "83ef80eaba1819328ed0090f487588f83a571fd630c485ebf619d1ad4876c5a8");
+  }
+
+  #[test]
+  fn test_distribution_uses_hasher() {
+    let address = String::from("zil1p5suryq6q647usxczale29cu3336hhp376c627");
+    let distribution = Distribution::new(address, BigDecimal::from(1_000_000), &Keccak256Hasher).unwrap();
+    assert_eq!(distribution.address_hex(), ADDRESS);
+    assert_eq!(encode(distribution.hash()), "10aeefe52e754a83a57500054c4942611ffaab3113c81085d69a049f91ffdac4");
+  }
+
+  #[test]
+  fn test_amounts_must_fit_uint128() {
+    let address = String::from("zil1p5suryq6q647usxczale29cu3336hhp376c627");
+    let max = BigDecimal::from_str("340282366920938463463374607431768211455").unwrap();
+    assert!(Distribution::new(address.clone(), max.clone(), &Sha256Hasher).is_ok());
+
+    let err = Distribution::new(address.clone(), max + BigDecimal::from(1), &Sha256Hasher).err().unwrap();
+    assert_eq!(err.to_string(), format!("Amount 340282366920938463463374607431768211456 for {} does not fit in a uint128", address));
+    assert!(Distribution::new(address.clone(), BigDecimal::from(-1), &Sha256Hasher).is_err());
+    assert!(Distribution::new(address, BigDecimal::from_str("1.5").unwrap(), &Sha256Hasher).is_err());
+  }
+
+  #[test]
+  fn test_every_proof_verifies() {
+    // odd sizes carry nodes up unpaired at one or more levels
+    for num_leaves in 1..=7 {
+      for hasher in [HashScheme::Sha256.hasher(), HashScheme::Keccak256.hasher()] {
+        let leaves: Vec<Distribution> = (1..=num_leaves)
+          .map(|i| Distribution::new(crate::test_utils::random_address(), BigDecimal::from(i * 1_000_000), hasher).unwrap())
+          .collect();
+        let tree = construct_merkle_tree(leaves.clone(), hasher);
+        let root = tree.root().data().1.clone();
+        let proofs = get_proofs(tree);
+        assert_eq!(proofs.len(), leaves.len());
+        for (leaf, proof) in proofs.iter() {
+          assert!(verify_proof(leaf, proof, &root, hasher), "invalid proof for leaf of {} leaves: {}", num_leaves, proof);
+        }
+
+        // the tree does not depend on the order of the leaves
+        let reversed = construct_merkle_tree(leaves.into_iter().rev().collect(), hasher);
+        assert_eq!(reversed.root().data().1, root);
+      }
+    }
+  }
+
+  #[test]
+  fn test_tampered_proofs_fail_verification() {
+    let hasher = HashScheme::Sha256.hasher();
+    let leaves: Vec<Distribution> = (1..=3)
+      .map(|i| Distribution::new(crate::test_utils::random_address(), BigDecimal::from(i * 1_000_000), hasher).unwrap())
+      .collect();
+    let tree = construct_merkle_tree(leaves, hasher);
+    let root = tree.root().data().1.clone();
+    let (leaf, proof) = get_proofs(tree).into_iter().next().unwrap();
+    assert!(verify_proof(&leaf, &proof, &root, hasher));
+
+    let tampered_leaf = Distribution::new(leaf.address_bech32().to_owned(), leaf.amount() + BigDecimal::from(1), hasher).unwrap();
+    assert!(!verify_proof(&tampered_leaf, &proof, &root, hasher));
+
+    // a proof rebuilt for the tampered leaf does not lead to the root either
+    let tampered_proof = proof.replacen(&encode(leaf.hash()), &encode(tampered_leaf.hash()), 1);
+    assert!(!verify_proof(&tampered_leaf, &tampered_proof, &root, hasher));
+
+    let other_root = hasher.digest(b"other root");
+    assert!(!verify_proof(&leaf, &proof, &other_root, hasher));
+  }
+
+  #[test]
+  fn test_epoch_period_must_be_at_least_min_epoch_period() {
+    assert!(emission(604_800).validate().is_ok());
+    assert!(emission(3600).validate().is_ok());
+    for epoch_period in [0, -1, 3599] {
+      let err = emission(epoch_period).validate().err().unwrap();
+      assert_eq!(err.details, "epoch_period must be at least 3600 seconds");
+    }
+  }
+}
@@ -0,0 +1,112 @@
+use actix_web::{HttpResponse, ResponseError};
+use actix_web::error::{BlockingError, JsonPayloadError};
+use actix_web::http::{StatusCode};
+use serde_json::json;
+use std::fmt;
+
+/// Errors returned by api handlers, serialized as
+/// `{"error": {"code": ..., "message": ...}}`.
+#[derive(Debug)]
+pub enum ApiError {
+  BadRequest(String),
+  Unauthorized(String),
+  NotFound(String),
+  PayloadTooLarge(String),
+  TooManyRequests(String),
+  ServiceUnavailable(String),
+  Internal(String),
+}
+
+impl ApiError {
+  fn code(&self) -> &str {
+    match *self {
+      ApiError::BadRequest(_) => "bad_request",
+      ApiError::Unauthorized(_) => "unauthorized",
+      ApiError::NotFound(_) => "not_found",
+      ApiError::PayloadTooLarge(_) => "payload_too_large",
+      ApiError::TooManyRequests(_) => "too_many_requests",
+      ApiError::ServiceUnavailable(_) => "service_unavailable",
+      ApiError::Internal(_) => "internal_error",
+    }
+  }
+
+  fn message(&self) -> &str {
+    match self {
+      ApiError::BadRequest(m) | ApiError::Unauthorized(m) | ApiError::NotFound(m) | ApiError::PayloadTooLarge(m) | ApiError::TooManyRequests(m) | ApiError::ServiceUnavailable(m) | ApiError::Internal(m) => m.as_str(),
+    }
+  }
+}
+
+impl fmt::Display for ApiError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}: {}", self.code(), self.message())
+  }
+}
+
+impl ResponseError for ApiError {
+  fn status_code(&self) -> StatusCode {
+    match *self {
+      ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+      ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+      ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+      ApiError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+      ApiError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+      ApiError::ServiceUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+      ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+  }
+
+  fn error_response(&self) -> HttpResponse {
+    HttpResponse::build(self.status_code()).json(json!({
+      "error": {
+        "code": self.code(),
+        "message": self.message(),
+      }
+    }))
+  }
+}
+
+impl From<diesel::result::Error> for ApiError {
+  fn from(err: diesel::result::Error) -> ApiError {
+    match err {
+      diesel::result::Error::NotFound => ApiError::NotFound(String::from("record not found")),
+      _ => {
+        error!("{}", err);
+        ApiError::Internal(String::from("database error"))
+      }
+    }
+  }
+}
+
+impl From<redis::RedisError> for ApiError {
+  fn from(err: redis::RedisError) -> ApiError {
+    error!("{}", err);
+    ApiError::ServiceUnavailable(String::from("cache unavailable"))
+  }
+}
+
+/// The database and redis pools share this error type, so the message does not name either.
+impl From<diesel::r2d2::PoolError> for ApiError {
+  fn from(err: diesel::r2d2::PoolError) -> ApiError {
+    error!("{}", err);
+    ApiError::ServiceUnavailable(String::from("service unavailable"))
+  }
+}
+
+impl<E: Into<ApiError> + fmt::Debug> From<BlockingError<E>> for ApiError {
+  fn from(err: BlockingError<E>) -> ApiError {
+    match err {
+      BlockingError::Error(e) => e.into(),
+      BlockingError::Canceled => ApiError::ServiceUnavailable(String::from("request canceled")),
+    }
+  }
+}
+
+impl From<JsonPayloadError> for ApiError {
+  fn from(err: JsonPayloadError) -> ApiError {
+    match err {
+      JsonPayloadError::Overflow => ApiError::PayloadTooLarge(String::from("Request body is larger than the allowed maximum")),
+      _ => ApiError::BadRequest(err.to_string()),
+    }
+  }
+}
@@ -0,0 +1,326 @@
+use bigdecimal::{BigDecimal, One, Signed};
+use serde::{Deserialize, Serialize};
+
+use crate::rpc::PoolReserves;
+use crate::utils;
+
+/// Digits kept after the decimal point of quoted slippage and price impact.
+const FRACTION_DIGITS: i64 = 8;
+
+/// The sides of a trade, and whether its input or output amount is exact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TradeDirection {
+  ExactZilForTokens,
+  ZilForExactTokens,
+  ExactTokensForZil,
+  TokensForExactZil,
+  ExactTokensForTokens,
+  TokensForExactTokens,
+}
+
+impl TradeDirection {
+  /// Token to token trades go through a second pool, swapping to and from zil.
+  pub fn is_token_to_token(self) -> bool {
+    matches!(self, TradeDirection::ExactTokensForTokens | TradeDirection::TokensForExactTokens)
+  }
+
+  pub fn is_exact_input(self) -> bool {
+    matches!(self, TradeDirection::ExactZilForTokens | TradeDirection::ExactTokensForZil | TradeDirection::ExactTokensForTokens)
+  }
+}
+
+/// The expected amounts of a trade, in base units. Slippage and price impact are
+/// fractions (e.g. 0.01 for 1%), not percentages. Slippage is the fraction of the output
+/// lost to moving the price, compared to trading at the current price after the fee.
+/// Price impact is how far the execution price (output / input) is below the spot price
+/// given by the ratio of the reserves before the trade, so it also includes the fee.
+#[derive(Debug, Serialize)]
+pub struct Quote {
+  pub expected_input: BigDecimal,
+  pub expected_output: BigDecimal,
+  pub expected_slippage: BigDecimal,
+  pub price_impact: BigDecimal,
+}
+
+/// A zilswap pool to quote trades against, using its constant product with a fee on inputs.
+#[derive(Debug, Clone)]
+pub struct LiquidityPool {
+  pub zil_reserve: BigDecimal,
+  pub token_reserve: BigDecimal,
+  pub fee_rate: BigDecimal,
+}
+
+/// The fee of zilswap pools, 0.3% of inputs.
+pub fn default_fee_rate() -> BigDecimal {
+  BigDecimal::new(3.into(), 3)
+}
+
+impl LiquidityPool {
+  /// The pool takes `fee_rate` of each input as a fee, e.g. 0.003 for 0.3%.
+  /// Returns `None` if the pool has no liquidity to trade against.
+  pub fn new(reserves: &PoolReserves, fee_rate: BigDecimal) -> Option<LiquidityPool> {
+    if !reserves.zil_reserve.is_positive() || !reserves.token_reserve.is_positive() {
+      return None
+    }
+    Some(LiquidityPool {
+      zil_reserve: reserves.zil_reserve.clone(),
+      token_reserve: reserves.token_reserve.clone(),
+      fee_rate,
+    })
+  }
+
+  /// The fraction of an input that is swapped after the fee.
+  fn fee_multiplier(&self) -> BigDecimal {
+    BigDecimal::one() - &self.fee_rate
+  }
+
+  /// The (input reserve, output reserve, fee multiplier) of each swap of a trade, in order.
+  /// Returns `None` for token to token trades without an `out_pool`.
+  fn hops<'a>(&'a self, direction: TradeDirection, out_pool: Option<&'a LiquidityPool>) -> Option<Vec<(&'a BigDecimal, &'a BigDecimal, BigDecimal)>> {
+    let hops = match direction {
+      TradeDirection::ExactZilForTokens | TradeDirection::ZilForExactTokens => vec![(&self.zil_reserve, &self.token_reserve, self.fee_multiplier())],
+      TradeDirection::ExactTokensForZil | TradeDirection::TokensForExactZil => vec![(&self.token_reserve, &self.zil_reserve, self.fee_multiplier())],
+      TradeDirection::ExactTokensForTokens | TradeDirection::TokensForExactTokens => {
+        let out_pool = out_pool?;
+        vec![
+          // the input token is sold for zil from this pool's zil reserve,
+          (&self.token_reserve, &self.zil_reserve, self.fee_multiplier()),
+          // and that zil buys the output token from `out_pool`'s token reserve
+          (&out_pool.zil_reserve, &out_pool.token_reserve, out_pool.fee_multiplier()),
+        ]
+      },
+    };
+    Some(hops)
+  }
+
+  /// Quotes a trade of `amount`, which is the input for exact input directions and the
+  /// output otherwise. Token to token trades also go through `out_pool`. Returns `None`
+  /// if the trade cannot be made, as it needs more than the reserves of a pool.
+  pub fn quote(&self, direction: TradeDirection, amount: &BigDecimal, out_pool: Option<&LiquidityPool>) -> Option<Quote> {
+    let hops = self.hops(direction, out_pool)?;
+    let (input, output) = trade_amounts(&hops, direction, amount)?;
+
+    // the output per unit of input for a trade too small to move the price
+    let price = hops.iter().fold(BigDecimal::one(), |price, (input_reserve, output_reserve, fee_multiplier)| {
+      price * fee_multiplier * (*output_reserve) / (*input_reserve)
+    });
+    let slippage = BigDecimal::one() - &output / &input / price;
+    let price_impact = self.price_impact(direction, amount, out_pool)?;
+
+    Some(Quote {
+      expected_input: input,
+      expected_output: output,
+      expected_slippage: utils::round_down(slippage, FRACTION_DIGITS),
+      price_impact,
+    })
+  }
+
+  /// The price impact of a trade as a fraction, e.g. 0.01 for 1%: how far its execution
+  /// price (output / input) is below the spot price given by the ratio of the reserves
+  /// before the trade. Unlike slippage, this includes the fee. Returns `None` if the
+  /// trade cannot be made.
+  pub fn price_impact(&self, direction: TradeDirection, amount: &BigDecimal, out_pool: Option<&LiquidityPool>) -> Option<BigDecimal> {
+    let hops = self.hops(direction, out_pool)?;
+    let (input, output) = trade_amounts(&hops, direction, amount)?;
+    let spot_price = hops.iter().fold(BigDecimal::one(), |price, (input_reserve, output_reserve, _)| {
+      price * (*output_reserve) / (*input_reserve)
+    });
+    let price_impact = BigDecimal::one() - &output / &input / spot_price;
+    Some(utils::round_down(price_impact, FRACTION_DIGITS))
+  }
+}
+
+/// The (input, output) of a trade of `amount` through `hops`. Returns `None` if the trade
+/// needs more than the reserves of a pool.
+fn trade_amounts(hops: &[(&BigDecimal, &BigDecimal, BigDecimal)], direction: TradeDirection, amount: &BigDecimal) -> Option<(BigDecimal, BigDecimal)> {
+  let (input, output) = if direction.is_exact_input() {
+    let output = hops.iter().try_fold(amount.clone(), |input, (input_reserve, output_reserve, fee_multiplier)| {
+      Some(output_for(&input, input_reserve, output_reserve, fee_multiplier))
+    })?;
+    (amount.clone(), output)
+  } else {
+    let input = hops.iter().rev().try_fold(amount.clone(), |output, (input_reserve, output_reserve, fee_multiplier)| {
+      input_for(&output, input_reserve, output_reserve, fee_multiplier)
+    })?;
+    (input, amount.clone())
+  };
+  if !input.is_positive() {
+    return None
+  }
+  Some((input, output))
+}
+
+/// The output of swapping exactly `input`, rounded down like the contract does.
+fn output_for(input: &BigDecimal, input_reserve: &BigDecimal, output_reserve: &BigDecimal, fee_multiplier: &BigDecimal) -> BigDecimal {
+  let input_after_fee = input * fee_multiplier;
+  utils::round_down(&input_after_fee * output_reserve / (input_reserve + &input_after_fee), 0)
+}
+
+/// The input needed to swap for exactly `output`, rounded up like the contract does.
+/// Returns `None` if the output is not less than the output reserve.
+fn input_for(output: &BigDecimal, input_reserve: &BigDecimal, output_reserve: &BigDecimal, fee_multiplier: &BigDecimal) -> Option<BigDecimal> {
+  if output >= output_reserve {
+    return None
+  }
+  let input = input_reserve * output / ((output_reserve - output) * fee_multiplier);
+  let rounded = utils::round_down(input.clone(), 0);
+  if rounded == input {
+    Some(rounded)
+  } else {
+    Some(rounded + BigDecimal::one())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::str::FromStr;
+
+  fn pool(zil_reserve: i64, token_reserve: i64, fee_rate: BigDecimal) -> LiquidityPool {
+    let reserves = PoolReserves {
+      token_address: String::from("zil1p5suryq6q647usxczale29cu3336hhp376c627"),
+      zil_reserve: BigDecimal::from(zil_reserve),
+      token_reserve: BigDecimal::from(token_reserve),
+      fee_rate: None,
+    };
+    LiquidityPool::new(&reserves, fee_rate).unwrap()
+  }
+
+  #[test]
+  fn test_default_fee_rate_is_exact() {
+    let pool = pool(1_000_000, 1_000_000, default_fee_rate());
+    assert_eq!(pool.fee_rate, BigDecimal::from_str("0.003").unwrap());
+    assert_eq!(pool.fee_rate.as_bigint_and_exponent(), (3.into(), 3));
+  }
+
+  #[test]
+  fn test_fee_rate() {
+    // 1000 * 1000000 / (1000000 + 1000) without a fee, and with 1000 * 0.99 swapped at a 1% fee
+    let quote = pool(1_000_000, 1_000_000, BigDecimal::default()).quote(TradeDirection::ExactZilForTokens, &BigDecimal::from(1000), None).unwrap();
+    assert_eq!(quote.expected_output, BigDecimal::from(999));
+    let quote = pool(1_000_000, 1_000_000, BigDecimal::from_str("0.01").unwrap()).quote(TradeDirection::ExactZilForTokens, &BigDecimal::from(1000), None).unwrap();
+    assert_eq!(quote.expected_output, BigDecimal::from(989));
+  }
+
+  #[test]
+  fn test_output_for_and_input_for() {
+    let fee_multiplier = BigDecimal::one() - default_fee_rate();
+    let (input_reserve, output_reserve) = (BigDecimal::from(1_000_000), BigDecimal::from(2_000_000));
+    assert_eq!(output_for(&BigDecimal::from(0), &input_reserve, &output_reserve, &fee_multiplier), BigDecimal::from(0));
+    // 997 * 2000000 / (1000000 + 997) = 1992.01
+    assert_eq!(output_for(&BigDecimal::from(1000), &input_reserve, &output_reserve, &fee_multiplier), BigDecimal::from(1992));
+    // 1000000 * 1992 / ((2000000 - 1992) * 0.997) = 999.993, rounded up
+    assert_eq!(input_for(&BigDecimal::from(1992), &input_reserve, &output_reserve, &fee_multiplier), Some(BigDecimal::from(1000)));
+
+    // exact inputs are not rounded up
+    assert_eq!(input_for(&BigDecimal::from(1000), &BigDecimal::from(1000), &BigDecimal::from(2000), &BigDecimal::one()), Some(BigDecimal::from(1000)));
+
+    // the input for an output always swaps for at least that output
+    for output in [1, 10, 999, 12_345, 1_000_000, 1_999_999] {
+      let output = BigDecimal::from(output);
+      let input = input_for(&output, &input_reserve, &output_reserve, &fee_multiplier).unwrap();
+      assert!(output_for(&input, &input_reserve, &output_reserve, &fee_multiplier) >= output);
+      assert!(output_for(&(input - BigDecimal::one()), &input_reserve, &output_reserve, &fee_multiplier) < output);
+    }
+
+    assert_eq!(input_for(&output_reserve, &input_reserve, &output_reserve, &fee_multiplier), None);
+  }
+
+  #[test]
+  fn test_exact_quote() {
+    let pool = pool(1_000_000, 1_000_000, default_fee_rate());
+
+    // 997 * 1000000 / (1000000 + 997) = 996.006 tokens, at 0.996 per zil against 0.997 after the fee,
+    // so 1 - 0.996 / 0.997 = 0.001003009 of the output is lost to slippage, and the price impact
+    // against the spot price of 1 is 1 - 0.996 = 0.004
+    let quote = pool.quote(TradeDirection::ExactZilForTokens, &BigDecimal::from(1000), None).unwrap();
+    assert_eq!(quote.expected_input, BigDecimal::from(1000));
+    assert_eq!(quote.expected_output, BigDecimal::from(996));
+    assert_eq!(quote.expected_slippage, BigDecimal::from_str("0.001003").unwrap());
+    assert_eq!(quote.price_impact, BigDecimal::from_str("0.004").unwrap());
+
+    // 996 tokens need 1000000 * 996 / ((1000000 - 996) * 0.997) = 999.993 zil
+    let quote = pool.quote(TradeDirection::ZilForExactTokens, &BigDecimal::from(996), None).unwrap();
+    assert_eq!(quote.expected_input, BigDecimal::from(1000));
+    assert_eq!(quote.expected_output, BigDecimal::from(996));
+    assert_eq!(quote.expected_slippage, BigDecimal::from_str("0.001003").unwrap());
+  }
+
+  #[test]
+  fn test_pools_without_liquidity() {
+    for (zil_reserve, token_reserve) in [(0, 1000), (1000, 0), (0, 0)] {
+      let reserves = PoolReserves {
+        token_address: String::from("zil1p5suryq6q647usxczale29cu3336hhp376c627"),
+        zil_reserve: BigDecimal::from(zil_reserve),
+        token_reserve: BigDecimal::from(token_reserve),
+        fee_rate: None,
+      };
+      assert!(LiquidityPool::new(&reserves, default_fee_rate()).is_none());
+    }
+
+    // an output of the whole reserve or more cannot be bought
+    let pool = pool(1000, 1000, default_fee_rate());
+    assert!(pool.quote(TradeDirection::ZilForExactTokens, &BigDecimal::from(1000), None).is_none());
+    assert!(pool.quote(TradeDirection::ZilForExactTokens, &BigDecimal::from(2000), None).is_none());
+  }
+
+  #[test]
+  fn test_token_to_token() {
+    // tokens are sold for zil in the first pool, and that zil is sold for tokens in the second
+    let in_pool = pool(1_000_000, 2_000_000, default_fee_rate());
+    let out_pool = pool(500_000, 100_000, default_fee_rate());
+
+    // 10000 * 0.997 * 1000000 / (2000000 + 9970) = 4960.27 zil, then
+    // 4960 * 0.997 * 100000 / (500000 + 4945.12) = 979.34 tokens
+    let quote = in_pool.quote(TradeDirection::ExactTokensForTokens, &BigDecimal::from(10_000), Some(&out_pool)).unwrap();
+    assert_eq!(quote.expected_input, BigDecimal::from(10_000));
+    assert_eq!(quote.expected_output, BigDecimal::from(979));
+
+    // 979 tokens need 500000 * 979 / ((100000 - 979) * 0.997) = 4958.27 zil, and
+    // 4959 zil need 2000000 * 4959 / ((1000000 - 4959) * 0.997) = 9997.42 tokens
+    let quote = in_pool.quote(TradeDirection::TokensForExactTokens, &BigDecimal::from(979), Some(&out_pool)).unwrap();
+    assert_eq!(quote.expected_input, BigDecimal::from(9_998));
+    assert_eq!(quote.expected_output, BigDecimal::from(979));
+
+    assert!(in_pool.quote(TradeDirection::ExactTokensForTokens, &BigDecimal::from(10_000), None).is_none());
+  }
+
+  #[test]
+  fn test_token_to_token_chains_pools() {
+    let in_pool = pool(1_000_000, 2_000_000, default_fee_rate());
+    let out_pool = pool(500_000, 100_000, default_fee_rate());
+    let amount = BigDecimal::from(10_000);
+
+    // the zil bought in the first pool is what is sold in the second
+    let zil = in_pool.quote(TradeDirection::ExactTokensForZil, &amount, None).unwrap().expected_output;
+    let tokens = out_pool.quote(TradeDirection::ExactZilForTokens, &zil, None).unwrap().expected_output;
+    let quote = in_pool.quote(TradeDirection::ExactTokensForTokens, &amount, Some(&out_pool)).unwrap();
+    assert_eq!(quote.expected_output, tokens);
+
+    // and for an exact output, the zil needed in the second pool is what is bought in the first
+    let zil = out_pool.quote(TradeDirection::ZilForExactTokens, &tokens, None).unwrap().expected_input;
+    let input = in_pool.quote(TradeDirection::TokensForExactZil, &zil, None).unwrap().expected_input;
+    let quote = in_pool.quote(TradeDirection::TokensForExactTokens, &tokens, Some(&out_pool)).unwrap();
+    assert_eq!(quote.expected_input, input);
+  }
+
+  #[test]
+  fn test_price_impact() {
+    let price_impact = |liquidity_pool: &LiquidityPool, direction: TradeDirection, amount: i64| {
+      liquidity_pool.price_impact(direction, &BigDecimal::from(amount), None).unwrap()
+    };
+
+    // 996 tokens for 1000 zil at a spot price of 1
+    let liquidity_pool = pool(1_000_000, 1_000_000, default_fee_rate());
+    assert_eq!(price_impact(&liquidity_pool, TradeDirection::ExactZilForTokens, 1000), BigDecimal::from_str("0.004").unwrap());
+
+    // 4960 zil for 10000 tokens at a spot price of 0.5 zil per token
+    let liquidity_pool = pool(1_000_000, 2_000_000, default_fee_rate());
+    assert_eq!(price_impact(&liquidity_pool, TradeDirection::ExactTokensForZil, 10_000), BigDecimal::from_str("0.008").unwrap());
+
+    // 499 tokens for 1000 zil, half of the pool's zil
+    let liquidity_pool = pool(1000, 1000, default_fee_rate());
+    assert_eq!(price_impact(&liquidity_pool, TradeDirection::ExactZilForTokens, 1000), BigDecimal::from_str("0.501").unwrap());
+  }
+}
@@ -13,18 +13,30 @@ embed_migrations!();
 #[macro_use]
 extern crate log;
 
+#[macro_use]
+extern crate lazy_static;
+
+#[macro_use]
+extern crate prometheus;
+
 extern crate redis;
 
-use actix::{Actor};
+use actix::{Actor, Addr};
 use actix_cors::{Cors};
-use actix_web::{get, web, App, Error, HttpResponse, HttpServer, Responder, middleware::Logger};
+use actix_web::{get, post, web, App, Error, HttpRequest, HttpResponse, HttpServer, Responder, middleware::{Compress}};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::dev::{Service};
 use bigdecimal::{BigDecimal, Signed};
 use diesel::prelude::*;
 use diesel::r2d2::{self, ConnectionManager};
 use hex::{encode};
+use ring::{constant_time};
 use serde::{Deserialize};
-use std::collections::HashMap;
-use std::time::{SystemTime};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+use futures::FutureExt;
 use redis::Commands;
 
 mod db;
@@ -37,17 +49,71 @@ mod pagination;
 mod distribution;
 mod utils;
 mod rpc;
+mod metrics;
+mod errors;
+mod tokens;
+mod oracle;
+mod liquidity_pool;
+#[cfg(test)]
+mod test_utils;
 
 use crate::constants::{Network};
-use crate::worker::{WorkerConfig};
-use crate::distribution::{EpochInfo, Distribution, DistributionConfigs, Validate};
+use crate::errors::{ApiError};
+use crate::pagination::{Cursor};
+use crate::tokens::{TokenAllowlist, TokenList};
+use crate::oracle::{PriceOracle};
+use crate::liquidity_pool::{LiquidityPool, TradeDirection};
+use crate::worker::{WorkerConfig, WorkerStatus};
+use crate::distribution::{EpochAmounts, EpochInfo, Distribution, DistributionConfig, DistributionConfigs, Validate};
 
 type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
+type RedisPool = r2d2::Pool<redis::Client>;
+
+/// Seconds to cache the pools overview for.
+const POOLS_CACHE_TTL_SECS: usize = 30;
+
+/// Cache key and seconds to cache the zil price in usd for.
+const PRICE_CACHE_KEY: &str = "zap-api-cache:zil_usd_price";
+const PRICE_CACHE_TTL_SECS: usize = 60;
+
+/// Maximum number of addresses that can be estimated in one request.
+const MAX_ESTIMATE_ADDRESSES: usize = 100;
+
+/// Limits how many epoch generations may run at the same time.
+struct GenerateLimit {
+  running: AtomicUsize,
+  max: usize,
+}
+
+/// A running epoch generation, which frees its slot when dropped.
+struct GeneratePermit<'a>(&'a AtomicUsize);
+
+impl GenerateLimit {
+  fn new(max: usize) -> Self {
+    Self { running: AtomicUsize::new(0), max }
+  }
+
+  fn try_acquire(&self) -> Option<GeneratePermit<'_>> {
+    let max = self.max;
+    self.running
+      .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| if n < max { Some(n + 1) } else { None })
+      .ok()
+      .map(|_| GeneratePermit(&self.running))
+  }
+}
+
+impl Drop for GeneratePermit<'_> {
+  fn drop(&mut self) {
+    self.0.fetch_sub(1, Ordering::SeqCst);
+  }
+}
 
 #[derive(Deserialize)]
 struct PaginationInfo {
   per_page: Option<i64>,
   page: Option<i64>,
+  cursor: Option<String>,
+  as_of: Option<i64>,
 }
 
 #[derive(Deserialize)]
@@ -56,11 +122,84 @@ struct AddressInfo {
   address: Option<String>,
 }
 
+#[derive(Deserialize)]
+struct LiquidityChangeInfo {
+  #[serde(rename = "type")]
+  change_type: Option<db::LiquidityChangeType>,
+}
+
 #[derive(Deserialize)]
 struct SwapInfo {
   pool: Option<String>,
   address: Option<String>,
   is_incoming: Option<bool>,
+  success: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct BlockRangeInfo {
+  from_block: Option<i32>,
+  to_block: Option<i32>,
+}
+
+impl BlockRangeInfo {
+  fn range(&self) -> Result<db::BlockRange, ApiError> {
+    if [self.from_block, self.to_block].iter().flatten().any(|h| *h < 0) {
+      return Err(ApiError::BadRequest(String::from("from_block and to_block must be non-negative")))
+    }
+    Ok(db::BlockRange { from: self.from_block, to: self.to_block })
+  }
+}
+
+#[derive(Deserialize)]
+struct ExclusionInfo {
+  exclude_wash_trades: Option<bool>,
+  exclude_zero_amounts: Option<bool>,
+}
+
+impl ExclusionInfo {
+  fn exclusions(&self) -> db::SwapExclusions {
+    db::SwapExclusions {
+      wash_trades: self.exclude_wash_trades.unwrap_or(false),
+      zero_amounts: self.exclude_zero_amounts.unwrap_or(false),
+    }
+  }
+}
+
+#[derive(Deserialize)]
+struct DenominationInfo {
+  denominate: Option<String>,
+  quote: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct IncludeInfo {
+  include: Option<String>,
+}
+
+impl IncludeInfo {
+  fn token_meta(&self) -> bool {
+    self.include.iter().flat_map(|i| i.split(',')).any(|i| i == "token_meta")
+  }
+}
+
+#[derive(Deserialize)]
+struct CacheInfo {
+  nocache: Option<String>,
+}
+
+impl CacheInfo {
+  fn bypass(&self) -> bool {
+    matches!(self.nocache.as_deref(), Some("true") | Some("t") | Some("1"))
+  }
+}
+
+#[derive(Deserialize)]
+struct TradeInfo {
+  pool: String,
+  out_pool: Option<String>,
+  amount: BigDecimal,
+  direction: TradeDirection,
 }
 
 #[derive(Deserialize)]
@@ -68,6 +207,11 @@ struct TimeInfo {
   timestamp: Option<i64>,
 }
 
+#[derive(Deserialize)]
+struct HeightInfo {
+  block: Option<i32>,
+}
+
 #[derive(Deserialize)]
 struct PeriodInfo {
   from: Option<i64>,
@@ -79,6 +223,17 @@ struct ClaimInfo {
   address: Option<String>,
   distr_address: Option<String>,
   epoch_number: Option<i32>,
+  sort: Option<db::ClaimSort>,
+  order: Option<db::SortOrder>,
+}
+
+#[derive(Deserialize)]
+struct ProofInfo {
+  distr_address: String,
+  epoch_number: i32,
+  address: String,
+  amount: BigDecimal,
+  proof: String,
 }
 
 /// Test endpoint.
@@ -87,119 +242,556 @@ async fn hello() -> impl Responder {
     HttpResponse::Ok().body("Hello zap!")
 }
 
-/// Gets swaps.
+/// Gets swaps, optionally excluding suspected wash trades and zero-amount swaps.
+/// Token names and symbols are embedded if `include=token_meta` is given.
 #[get("/swaps")]
 async fn get_swaps(
     query: web::Query<PaginationInfo>,
     filter: web::Query<SwapInfo>,
+    exclusion: web::Query<ExclusionInfo>,
+    blocks: web::Query<BlockRangeInfo>,
+    include: web::Query<IncludeInfo>,
     pool: web::Data<DbPool>,
-) -> Result<HttpResponse, Error> {
+    tokens: web::Data<TokenList>,
+) -> Result<HttpResponse, ApiError> {
+    let exclusions = exclusion.exclusions();
+    let block_range = blocks.range()?;
+    let address = address_filter(filter.address.as_deref())?;
+    let pool_address = pools_filter(filter.pool.as_deref())?;
+
+    // an empty cursor requests the first page by keyset
+    if let Some(cursor) = query.cursor.clone() {
+      let cursor = match cursor.as_str() {
+        "" => None,
+        c => Some(Cursor::decode(c).ok_or(ApiError::BadRequest(String::from("Invalid cursor")))?),
+      };
+      let swaps = web::block(move || {
+        let conn = get_conn(&pool)?;
+        db::get_swaps_after(&conn, query.per_page, cursor, pool_address.as_deref(), address.as_deref(), filter.is_incoming.as_ref(), filter.success.as_ref(), exclusions, block_range, query.as_of).map_err(ApiError::from)
+      })
+      .await?;
+
+      if include.token_meta() {
+        return Ok(HttpResponse::Ok().json(swaps.map(|s| tokens.with_meta(&s.token_address.clone(), s))))
+      }
+      return Ok(HttpResponse::Ok().json(swaps))
+    }
+
     let swaps = web::block(move || {
-      let conn = pool.get().expect("couldn't get db connection from pool");
-      db::get_swaps(&conn, query.per_page, query.page, filter.pool.as_deref(), filter.address.as_deref(), filter.is_incoming.as_ref())
+      let conn = get_conn(&pool)?;
+      db::get_swaps(&conn, query.per_page, query.page, pool_address.as_deref(), address.as_deref(), filter.is_incoming.as_ref(), filter.success.as_ref(), exclusions, block_range, query.as_of).map_err(ApiError::from)
     })
-    .await.map_err(|e| {
-      eprintln!("{}", e);
-      HttpResponse::InternalServerError().finish()
-    })?;
+    .await?;
 
+    if include.token_meta() {
+      return Ok(HttpResponse::Ok().json(swaps.map(|s| tokens.with_meta(&s.token_address.clone(), s))))
+    }
     Ok(HttpResponse::Ok().json(swaps))
 }
 
-/// Get liquidity changes.
+/// Get liquidity changes, optionally only those that add or remove liquidity with `type=add|remove`.
 #[get("/liquidity_changes")]
 async fn get_liquidity_changes(
   query: web::Query<PaginationInfo>,
   filter: web::Query<AddressInfo>,
+  change: web::Query<LiquidityChangeInfo>,
+  blocks: web::Query<BlockRangeInfo>,
   pool: web::Data<DbPool>,
-) -> Result<HttpResponse, Error> {
+) -> Result<HttpResponse, ApiError> {
+  let address = address_filter(filter.address.as_deref())?;
+  let pool_address = address_filter(filter.pool.as_deref())?;
+  let block_range = blocks.range()?;
   let liquidity_changes = web::block(move || {
-    let conn = pool.get().expect("couldn't get db connection from pool");
-    db::get_liquidity_changes(&conn, query.per_page, query.page, filter.pool.as_deref(), filter.address.as_deref())
+    let conn = get_conn(&pool)?;
+    db::get_liquidity_changes(&conn, query.per_page, query.page, pool_address.as_deref(), address.as_deref(), change.change_type, block_range).map_err(ApiError::from)
   })
-  .await.map_err(|e| {
-    eprintln!("{}", e);
-    HttpResponse::InternalServerError().finish()
-  })?;
+  .await?;
 
   Ok(HttpResponse::Ok().json(liquidity_changes))
 }
 
 /// Get the swap volume in zil / tokens for the given period for all pools.
+/// If `denominate=zil` is given, the volume of each pool is instead returned as a single zil amount.
+/// If `quote=usd` is given, the zil amounts are also valued in usd, unless the price oracle is unavailable.
+/// Suspected wash trades and zero-amount swaps are excluded if `exclude_wash_trades=true`
+/// or `exclude_zero_amounts=true` is given.
 #[get("/volume")]
 async fn get_volume(
   query: web::Query<PeriodInfo>,
   filter: web::Query<AddressInfo>,
+  denomination: web::Query<DenominationInfo>,
+  exclusion: web::Query<ExclusionInfo>,
   pool: web::Data<DbPool>,
-) -> Result<HttpResponse, Error> {
+  redis: web::Data<RedisPool>,
+  oracle: web::Data<PriceOracle>,
+) -> Result<HttpResponse, ApiError> {
+  let DenominationInfo { denominate: denom, quote } = denomination.into_inner();
+  if let Some(denom) = &denom {
+    if denom != "zil" {
+      return Err(ApiError::BadRequest(format!("Unsupported denomination: {}", denom)))
+    }
+  }
+  if let Some(quote) = &quote {
+    if quote != "usd" {
+      return Err(ApiError::BadRequest(format!("Unsupported quote: {}", quote)))
+    }
+    if denom.is_some() {
+      return Err(ApiError::BadRequest(String::from("quote cannot be combined with denominate")))
+    }
+  }
+
+  let address = address_filter(filter.address.as_deref())?;
   let volumes = web::block(move || {
-    let conn = pool.get().expect("couldn't get db connection from pool");
-    db::get_volume(&conn, filter.address.as_deref(), query.from, query.until)
+    let conn = get_conn(&pool)?;
+    db::get_volume(&conn, address.as_deref(), query.from, query.until, exclusion.exclusions()).map_err(ApiError::from)
   })
-  .await.map_err(|e| {
-    eprintln!("{}", e);
-    HttpResponse::InternalServerError().finish()
-  })?;
+  .await?;
+
+  if denom.is_some() {
+    let denominated: Vec<models::DenominatedVolume> = volumes.iter().map(|v| {
+      models::DenominatedVolume {
+        pool: v.pool.clone(),
+        denom: String::from("zil"),
+        amount: v.zil_amount(),
+      }
+    }).collect();
+    return Ok(HttpResponse::Ok().json(denominated))
+  }
+
+  if quote.is_some() {
+    if let Some(price) = get_zil_usd_price(&redis, &oracle).await {
+      let volumes: Vec<models::UsdVolume> = volumes.into_iter().map(|v| models::UsdVolume::new(v, &price)).collect();
+      return Ok(HttpResponse::Ok().json(volumes))
+    }
+  }
+
+  Ok(HttpResponse::Ok().json(volumes))
+}
+
+/// Get the swap volume in zil for the given period for all pools by a single address.
+#[get("/volume/by_address/{address}")]
+async fn get_volume_by_address(
+  query: web::Query<PeriodInfo>,
+  pool: web::Data<DbPool>,
+  web::Path(address): web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+  let address = parse_address(&address)?;
+  let volumes = web::block(move || {
+    let conn = get_conn(&pool)?;
+    db::get_volume_by_address(&conn, Some(&address), query.from, query.until).map_err(ApiError::from)
+  })
+  .await?;
 
   Ok(HttpResponse::Ok().json(volumes))
 }
 
+/// Get all pools with their current reserves, liquidity and 24h volume.
+#[get("/pools")]
+async fn get_pools(
+  pool: web::Data<DbPool>,
+  redis: web::Data<RedisPool>,
+  zil_client: web::Data<rpc::AsyncZilliqaClient>,
+  worker_config: web::Data<WorkerConfig>,
+  include: web::Query<IncludeInfo>,
+  tokens: web::Data<TokenList>,
+) -> Result<HttpResponse, ApiError> {
+  let network = std::env::var("NETWORK").unwrap_or(String::from("testnet"));
+  let cache_key = format!("zap-api-cache:{}:get_pools", network);
+
+  // token metadata is embedded after caching, so that it stays up to date
+  let respond = |pools: Vec<models::PoolInfo>| {
+    if include.token_meta() {
+      let pools: Vec<_> = pools.into_iter().map(|p| tokens.with_meta(&p.pool.clone(), p)).collect();
+      return HttpResponse::Ok().json(pools)
+    }
+    HttpResponse::Ok().json(pools)
+  };
+
+  let cache = redis.clone();
+  let key = cache_key.clone();
+  let cached = web::block(move || {
+    let mut rconn = cache.get()?;
+    let cache_value: Option<String> = rconn.get(key).unwrap_or(None);
+    Ok::<Option<Vec<models::PoolInfo>>, ApiError>(cache_value.and_then(|v| serde_json::from_str(&v).ok()))
+  })
+  .await?;
+  if let Some(pools) = cached {
+    return Ok(respond(pools))
+  }
+
+  // reserves are left out if the chain cannot be reached
+  let reserves: HashMap<String, rpc::PoolReserves> = zil_client.get_pool_reserves(worker_config.contract_address()).await
+    .unwrap_or_else(|e| {
+      warn!("Failed to fetch pool reserves: {}", e);
+      vec![]
+    })
+    .into_iter()
+    .map(|r| (r.token_address.clone(), r))
+    .collect();
+
+  let pools = web::block(move || {
+    let conn = get_conn(&pool)?;
+    let current_time = SystemTime::now()
+      .duration_since(SystemTime::UNIX_EPOCH)
+      .expect("invalid server time")
+      .as_secs() as i64;
+
+    let liquidity: HashMap<String, BigDecimal> = db::get_liquidity(&conn, None, None, None)?
+      .into_iter()
+      .map(|l| (l.pool, l.amount))
+      .collect();
+    let volumes: HashMap<String, BigDecimal> = db::get_volume(&conn, None, Some(current_time - 86400), None, db::SwapExclusions::default())?
+      .into_iter()
+      .map(|v| (v.pool.clone(), v.zil_amount()))
+      .collect();
+
+    let pools: Vec<models::PoolInfo> = db::get_pools(&conn)?.into_iter().map(|pool| {
+      let total_liquidity = liquidity.get(&pool).cloned().unwrap_or_default();
+      let reserves = reserves.get(&pool);
+      models::PoolInfo {
+        zil_reserve: reserves.map(|r| r.zil_reserve.clone()),
+        token_reserve: reserves.map(|r| r.token_reserve.clone()),
+        volume_24h: volumes.get(&pool).cloned().unwrap_or_default(),
+        has_liquidity: total_liquidity.is_positive(),
+        total_liquidity,
+        pool,
+      }
+    }).collect();
+
+    let mut rconn = redis.get()?;
+    let cache_value = serde_json::to_string(&pools).expect("failed to serialize result to cache");
+    if let Err(e) = rconn.set_ex::<String, String, ()>(cache_key, cache_value, POOLS_CACHE_TTL_SECS) {
+      error!("{}", e)
+    }
+
+    Ok::<Vec<models::PoolInfo>, ApiError>(pools)
+  })
+  .await?;
+
+  Ok(respond(pools))
+}
+
+/// Get the metadata of tokens listed on ZilStream. Empty if the list could not be fetched.
+#[get("/tokens")]
+async fn get_tokens(tokens: web::Data<TokenList>) -> impl Responder {
+  HttpResponse::Ok().json(tokens.all())
+}
+
+/// Quote a trade against the current reserves of a pool, or of two pools for token to token trades.
+/// The amount is the input for exact input directions and the output otherwise, in base units.
+/// If the node cannot be reached, the reserves from the latest indexed `PoolSynced` events are used.
+/// Tokens that are not on the quote allowlist are rejected, unless the allowlist is empty.
+#[get("/quote")]
+async fn get_quote(
+  query: web::Query<TradeInfo>,
+  pool: web::Data<DbPool>,
+  zil_client: web::Data<rpc::AsyncZilliqaClient>,
+  worker_config: web::Data<WorkerConfig>,
+  allowlist: web::Data<TokenAllowlist>,
+) -> Result<HttpResponse, ApiError> {
+  let TradeInfo { pool: in_pool, out_pool, amount, direction } = query.into_inner();
+  if !amount.is_positive() || !amount.is_integer() {
+    return Err(ApiError::BadRequest(String::from("amount must be a positive integer in base units")))
+  }
+  let in_pool = parse_address(&in_pool)?;
+  let out_pool = match (direction.is_token_to_token(), out_pool) {
+    (true, Some(out_pool)) => Some(parse_address(&out_pool)?),
+    (true, None) => return Err(ApiError::BadRequest(String::from("out_pool is required for token to token trades"))),
+    (false, Some(_)) => return Err(ApiError::BadRequest(String::from("out_pool is only used for token to token trades"))),
+    (false, None) => None,
+  };
+  if let Some(address) = std::iter::once(&in_pool).chain(out_pool.iter()).find(|address| !allowlist.allows(address)) {
+    return Err(ApiError::BadRequest(format!("Token {} is not on the quote allowlist", address)))
+  }
+
+  let reserves = get_pool_reserves(&pool, &zil_client, &worker_config).await?;
+  let liquidity_pool = |address: &str| {
+    let reserves = reserves.iter().find(|r| r.token_address == address)
+      .ok_or_else(|| ApiError::NotFound(format!("Unknown pool {}", address)))?;
+    let fee_rate = reserves.fee_rate.clone().unwrap_or_else(liquidity_pool::default_fee_rate);
+    LiquidityPool::new(reserves, fee_rate).ok_or_else(|| ApiError::BadRequest(format!("Pool {} has no liquidity", address)))
+  };
+  let in_pool = liquidity_pool(&in_pool)?;
+  let out_pool = out_pool.as_deref().map(liquidity_pool).transpose()?;
+
+  let quote = in_pool.quote(direction, &amount, out_pool.as_ref())
+    .ok_or_else(|| ApiError::BadRequest(String::from("Insufficient liquidity for the trade")))?;
+
+  Ok(HttpResponse::Ok().json(quote))
+}
+
+/// Get tickers in the CoinGecko format for the pools of tokens listed on ZilStream,
+/// as their decimals are needed to scale prices and volumes.
+#[get("/coingecko/tickers")]
+async fn get_coingecko_tickers(
+  pool: web::Data<DbPool>,
+  redis: web::Data<RedisPool>,
+  oracle: web::Data<PriceOracle>,
+  zil_client: web::Data<rpc::AsyncZilliqaClient>,
+  worker_config: web::Data<WorkerConfig>,
+  tokens: web::Data<TokenList>,
+) -> Result<HttpResponse, ApiError> {
+  let zil_usd_price = get_zil_usd_price(&redis, &oracle).await;
+  let zil_reserves: HashMap<String, BigDecimal> = get_pool_reserves(&pool, &zil_client, &worker_config).await?
+    .into_iter()
+    .map(|r| (r.token_address, r.zil_reserve))
+    .collect();
+
+  let tickers = web::block(move || {
+    let conn = get_conn(&pool)?;
+    let current_time = SystemTime::now()
+      .duration_since(SystemTime::UNIX_EPOCH)
+      .expect("invalid server time")
+      .as_secs() as i64;
+
+    let volumes: HashMap<String, models::Volume> = db::get_volume(&conn, None, Some(current_time - 86400), None, db::SwapExclusions::default())?
+      .into_iter()
+      .map(|v| (v.pool.clone(), v))
+      .collect();
+
+    let tickers: Vec<models::Ticker> = db::get_last_swaps(&conn)?.into_iter().filter_map(|swap| {
+      let token = tokens.meta(&swap.token_address)?;
+      let volume = volumes.get(&swap.token_address);
+      let zil_reserve = zil_reserves.get(&swap.token_address);
+      Some(models::Ticker::new(swap, volume, &token, zil_reserve, zil_usd_price.as_ref()))
+    }).collect();
+
+    Ok::<Vec<models::Ticker>, ApiError>(tickers)
+  })
+  .await?;
+
+  Ok(HttpResponse::Ok().json(tickers))
+}
+
 /// Get pool transactions including both swaps and liquidity changes.
 #[get("/transactions")]
 async fn get_transactions(
   query: web::Query<PeriodInfo>,
   pagination: web::Query<PaginationInfo>,
   filter: web::Query<AddressInfo>,
+  blocks: web::Query<BlockRangeInfo>,
   pool: web::Data<DbPool>,
-) -> Result<HttpResponse, Error> {
+) -> Result<HttpResponse, ApiError> {
+  let address = address_filter(filter.address.as_deref())?;
+  let pool_address = pools_filter(filter.pool.as_deref())?;
+  let block_range = blocks.range()?;
   let transactions = web::block(move || {
-    let conn = pool.get().expect("couldn't get db connection from pool");
-    db::get_transactions(&conn, filter.address.as_deref(), filter.pool.as_deref(), query.from, query.until, pagination.per_page, pagination.page)
+    let conn = get_conn(&pool)?;
+    db::get_transactions(&conn, address.as_deref(), pool_address.as_deref(), query.from, query.until, block_range, pagination.per_page, pagination.page).map_err(ApiError::from)
   })
-  .await.map_err(|e| {
-    eprintln!("load error {}", e);
-    HttpResponse::InternalServerError().finish()
-  })?;
+  .await?;
 
   Ok(HttpResponse::Ok().json(transactions))
 }
 
+/// Get the number of liquidity providers of each pool.
+#[get("/pools/lp_counts")]
+async fn get_lp_counts(
+  query: web::Query<TimeInfo>,
+  filter: web::Query<AddressInfo>,
+  pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+  let pool_address = address_filter(filter.pool.as_deref())?;
+  let lp_counts = web::block(move || {
+    let conn = get_conn(&pool)?;
+    db::count_lps_per_pool(&conn, query.timestamp, pool_address.as_deref()).map_err(ApiError::from)
+  })
+  .await?;
+
+  Ok(HttpResponse::Ok().json(lp_counts))
+}
+
+/// Get the first and latest activity of a pool.
+#[get("/pools/{pool}/lifespan")]
+async fn get_pool_lifespan(
+  pool: web::Data<DbPool>,
+  web::Path(token_address): web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+  let token_address = parse_address(&token_address)?;
+  let lifespan = web::block(move || {
+    let conn = get_conn(&pool)?;
+    match db::get_pool_lifespan(&conn, &token_address)? {
+      Some(lifespan) => Ok(lifespan),
+      None => Err(ApiError::NotFound(format!("No activity found for pool {}", token_address))),
+    }
+  })
+  .await?;
+
+  Ok(HttpResponse::Ok().json(lifespan))
+}
+
+/// Get the worker's configuration and sync position.
+#[get("/worker/status")]
+async fn get_worker_status(
+  pool: web::Data<DbPool>,
+  worker_config: web::Data<WorkerConfig>,
+) -> Result<HttpResponse, ApiError> {
+  let status = web::block(move || {
+    let conn = get_conn(&pool)?;
+    let last_sync_height = db::last_sync_height(&conn)?;
+    Ok::<WorkerStatus, ApiError>(worker_config.status(var_enabled("RUN_WORKER"), last_sync_height))
+  })
+  .await?;
+
+  Ok(HttpResponse::Ok().json(status))
+}
+
+/// Get the share of a pool's liquidity held by an address.
+#[get("/pools/{pool}/share/{address}")]
+async fn get_pool_share(
+  query: web::Query<TimeInfo>,
+  pool: web::Data<DbPool>,
+  web::Path((token_address, address)): web::Path<(String, String)>,
+) -> Result<HttpResponse, ApiError> {
+  let token_address = parse_address(&token_address)?;
+  let address = parse_address(&address)?;
+  let share = web::block(move || {
+    let conn = get_conn(&pool)?;
+    db::get_pool_share(&conn, &token_address, &address, query.timestamp).map_err(ApiError::from)
+  })
+  .await?;
+
+  Ok(HttpResponse::Ok().json(share))
+}
+
+/// Get events that the worker failed to persist.
+#[get("/failed_events")]
+async fn get_failed_events(
+  pagination: web::Query<PaginationInfo>,
+  pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+  let failed_events = web::block(move || {
+    let conn = get_conn(&pool)?;
+    db::get_failed_events(&conn, pagination.per_page, pagination.page).map_err(ApiError::from)
+  })
+  .await?;
+
+  Ok(HttpResponse::Ok().json(failed_events))
+}
+
+/// Queue any blocks missing from the synced blocks in the given range to be processed by the worker.
+#[get("/admin/backfill/{from}/{to}")]
+async fn backfill(
+  coordinator: web::Data<Option<Addr<worker::Coordinator>>>,
+  web::Path((from, to)): web::Path<(u32, u32)>,
+  req: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+  authorize(&req, "ADMIN_API_KEY")?;
+
+  if !var_enabled("RUN_BACKFILL") {
+    return Err(ApiError::ServiceUnavailable(String::from("Backfill disabled")))
+  }
+  if from > to {
+    return Err(ApiError::BadRequest(format!("Invalid block range {} - {}", from, to)))
+  }
+  let coordinator = coordinator.as_ref().as_ref()
+    .ok_or(ApiError::ServiceUnavailable(String::from("Worker is not running")))?;
+  coordinator.do_send(worker::Backfill { from, to });
+
+  Ok(HttpResponse::Accepted().finish())
+}
+
+/// Queue a failed event to be persisted again by the worker.
+#[post("/failed_events/{id}/reprocess")]
+async fn reprocess_failed_event(
+  coordinator: web::Data<Option<Addr<worker::Coordinator>>>,
+  web::Path(id): web::Path<uuid::Uuid>,
+  req: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+  authorize(&req, "ADMIN_API_KEY")?;
+
+  let coordinator = coordinator.as_ref().as_ref()
+    .ok_or(ApiError::ServiceUnavailable(String::from("Worker is not running")))?;
+  coordinator.do_send(worker::ReprocessFailedEvent { id });
+
+  Ok(HttpResponse::Accepted().finish())
+}
+
+/// Get the indexed block height against the chain height.
+#[get("/sync/status")]
+async fn get_sync_status(
+  pool: web::Data<DbPool>,
+  zil_client: web::Data<rpc::AsyncZilliqaClient>,
+) -> Result<HttpResponse, ApiError> {
+  let indexed_height = web::block(move || {
+    let conn = get_conn(&pool)?;
+    db::last_sync_height(&conn).map_err(ApiError::from)
+  })
+  .await?;
+
+  // cache the chain height briefly as this endpoint may be polled often
+  let chain_height = zil_client.get_latest_block_cached(Duration::from_secs(10)).await.map_err(|e| {
+    error!("{:?}", e);
+    ApiError::ServiceUnavailable(String::from("rpc node unavailable"))
+  })?;
+
+  Ok(HttpResponse::Ok().json(models::SyncStatus {
+    indexed_height,
+    chain_height,
+    lag: std::cmp::max(chain_height as i64 - indexed_height as i64, 0),
+  }))
+}
+
 /// Get liquidity for all pools.
 #[get("/liquidity")]
 async fn get_liquidity(
   query: web::Query<TimeInfo>,
+  height: web::Query<HeightInfo>,
   filter: web::Query<AddressInfo>,
   pool: web::Data<DbPool>,
-) -> Result<HttpResponse, Error> {
+) -> Result<HttpResponse, ApiError> {
+  if query.timestamp.is_some() && height.block.is_some() {
+    return Err(ApiError::BadRequest(String::from("timestamp cannot be combined with block")))
+  }
+  if height.block.iter().any(|h| *h < 0) {
+    return Err(ApiError::BadRequest(String::from("block must be non-negative")))
+  }
+  let address = address_filter(filter.address.as_deref())?;
   let liquidity = web::block(move || {
-    let conn = pool.get().expect("couldn't get db connection from pool");
-    db::get_liquidity(&conn, query.timestamp, filter.address.as_deref())
+    let conn = get_conn(&pool)?;
+    db::get_liquidity(&conn, query.timestamp, height.block, address.as_deref()).map_err(ApiError::from)
   })
-  .await.map_err(|e| {
-    eprintln!("{}", e);
-    HttpResponse::InternalServerError().finish()
-  })?;
+  .await?;
 
   Ok(HttpResponse::Ok().json(liquidity))
 }
 
-/// Get time-weighted liquidity for all pools.
+/// Get the liquidity added and removed in each pool over a period.
+#[get("/liquidity/flow")]
+async fn get_liquidity_flow(
+  query: web::Query<PeriodInfo>,
+  filter: web::Query<AddressInfo>,
+  pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+  let pool_address = address_filter(filter.pool.as_deref())?;
+  let flow = web::block(move || {
+    let conn = get_conn(&pool)?;
+    db::get_liquidity_flow(&conn, pool_address.as_deref(), query.from, query.until).map_err(ApiError::from)
+  })
+  .await?;
+
+  Ok(HttpResponse::Ok().json(flow))
+}
+
+/// Get time-weighted liquidity for all pools. The cache is bypassed if `nocache=1` is given.
 #[get("/weighted_liquidity")]
 async fn get_weighted_liquidity(
   query: web::Query<PeriodInfo>,
   filter: web::Query<AddressInfo>,
+  cache: web::Query<CacheInfo>,
   pool: web::Data<DbPool>,
-  redis: web::Data<redis::Client>,
-) -> Result<HttpResponse, Error> {
+  redis: web::Data<RedisPool>,
+) -> Result<HttpResponse, ApiError> {
+  let address = address_filter(filter.address.as_deref())?;
   let liquidity = web::block(move || {
-    let conn = pool.get().expect("couldn't get db connection from pool");
-    let mut rconn = redis.get_connection().expect("couldn't get redis connection");
-    db::get_time_weighted_liquidity(&conn, &mut rconn, query.from, query.until, filter.address.as_deref())
+    let conn = get_conn(&pool)?;
+    if cache.bypass() {
+      return db::get_uncached_time_weighted_liquidity(&conn, query.from, query.until, address.as_deref()).map_err(ApiError::from)
+    }
+    let mut rconn = redis.get()?;
+    Ok::<Vec<models::Liquidity>, ApiError>(db::get_time_weighted_liquidity(&conn, &mut rconn, query.from, query.until, address.as_deref())?)
   })
-  .await.map_err(|e| {
-    eprintln!("{}", e);
-    HttpResponse::InternalServerError().finish()
-  })?;
+  .await?;
 
   Ok(HttpResponse::Ok().json(liquidity))
 }
@@ -216,17 +808,26 @@ async fn get_weighted_liquidity(
 async fn generate_epoch(
   pool: web::Data<DbPool>,
   distr_config: web::Data<DistributionConfigs>,
-  redis: web::Data<redis::Client>,
+  redis: web::Data<RedisPool>,
   web::Path(id): web::Path<usize>,
-) -> Result<HttpResponse, Error> {
+  generate_limit: web::Data<GenerateLimit>,
+  req: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+  authorize(&req, "GENERATE_API_KEY")?;
+
   let result = web::block(move || {
-    let conn = pool.get().expect("couldn't get db connection from pool");
-    let mut rconn = redis.get_connection().expect("couldn't get redis connection");
+    let _permit = generate_limit.try_acquire()
+      .ok_or(ApiError::TooManyRequests(String::from("Too many epoch generations running, try again later")))?;
+    let conn = get_conn(&pool)?;
+    let mut rconn = redis.get()?;
     if !var_enabled("RUN_GENERATE") {
       return Ok(String::from("Epoch generation disabled!"))
     }
 
-    let distr = distr_config[id].clone();
+    let distr = match distr_config.get(id) {
+      Some(distr) => distr.clone(),
+      None => return Err(ApiError::NotFound(format!("No distribution with id {}", id))),
+    };
     let current_epoch = EpochInfo::new(distr.emission(), None);
     let current_epoch_number = current_epoch.epoch_number();
     let epoch_number = std::cmp::max(0, current_epoch_number - 1);
@@ -253,30 +854,26 @@ async fn generate_epoch(
     }
 
     // get pool TWAL and individual TWAL
-    struct PoolDistribution {
-      tokens: BigDecimal,
-      weighted_liquidity: BigDecimal,
-    }
     let pt = epoch_info.tokens_for_liquidity_providers();
-    let distribution: HashMap<String, PoolDistribution> =
+    let mut distribution: HashMap<String, PoolDistribution> =
       if epoch_info.is_initial() {
         let total_liquidity: BigDecimal = db::get_time_weighted_liquidity(&conn, &mut rconn, start, end, None)?.into_iter().map(|i| i.amount).sum();
         db::get_pools(&conn)?.into_iter().map(|pool| {
           (pool,
             PoolDistribution{ // share distribution fully
-              tokens: utils::round_down(pt.clone(), 0),
+              tokens: pt.clone(),
               weighted_liquidity: total_liquidity.clone(),
             }
           )
         }).collect()
       } else {
         let pool_weights = distr.incentivized_pools();
-        let total_weight: u32 = pool_weights.values().into_iter().sum();
+        let total_weight: u32 = pool_weights.values().sum();
         db::get_time_weighted_liquidity(&conn, &mut rconn, start, end, None)?.into_iter().filter_map(|i| {
           if let Some(weight) = pool_weights.get(&i.pool) {
             Some((i.pool,
               PoolDistribution{ // each pool has a weighted allocation
-                tokens: utils::round_down(pt.clone() * BigDecimal::from(*weight) / BigDecimal::from(total_weight), 0),
+                tokens: pt.clone() * BigDecimal::from(*weight) / BigDecimal::from(total_weight),
                 weighted_liquidity: i.amount,
               }
             ))
@@ -286,59 +883,35 @@ async fn generate_epoch(
         }).collect()
       };
 
-    let mut accumulator: HashMap<String, BigDecimal> = HashMap::new();
+    // pools without weighted liquidity have no one to share their tokens with
+    distribution.retain(|pool, d| {
+      if !d.weighted_liquidity.is_positive() {
+        warn!("Skipping liquidity rewards for pool {} as it has no weighted liquidity.", pool);
+        return false
+      }
+      true
+    });
 
-    // for each individual TWAL, calculate the tokens
     let user_liquidity = db::get_time_weighted_liquidity_by_address(&conn, start, end)?;
-    for l in user_liquidity.into_iter() {
-      if let Some(pool) = distribution.get(&l.pool) {
-        let share = utils::round_down(l.amount * pool.tokens.clone() / pool.weighted_liquidity.clone(), 0);
-        let current = accumulator.entry(l.address).or_insert(BigDecimal::default());
-        *current += share
-      }
-    }
 
     // if initial epoch, add distr for swap volumes
-    let tt = epoch_info.tokens_for_traders();
-    if tt.is_positive() {
-      let total_volume: BigDecimal = db::get_volume(&conn, None, start, end)?.into_iter().map(|v| v.in_zil_amount + v.out_zil_amount).sum();
-      let user_volume = db::get_volume_by_address(&conn, start, end)?;
-      for v in user_volume.into_iter() {
-        let share = utils::round_down(tt.clone() * v.amount.clone() / total_volume.clone(), 0);
-        let current = accumulator.entry(v.address).or_insert(BigDecimal::default());
-        *current += share
+    let mut total_volume = BigDecimal::default();
+    let mut user_volume = vec![];
+    if epoch_info.tokens_for_traders().is_positive() {
+      total_volume = db::get_volume(&conn, None, start, end, db::SwapExclusions::default())?.into_iter().map(|v| v.zil_amount()).sum();
+      if total_volume.is_positive() {
+        user_volume = db::get_volume_by_address(&conn, None, start, end)?;
+      } else {
+        warn!("Skipping trader rewards as there was no volume.");
       }
     }
 
-    // add developer share
-    let dt = epoch_info.tokens_for_developers();
-    if dt.is_positive() {
-      let current = accumulator.entry(distr.developer_address().to_owned()).or_insert(BigDecimal::default());
-      *current += dt
-    }
-
-    // override liquidity rewards to contract
-    let hive_address = "zil10mmqxduremmhyz2j89qptk3x8f2srw8rqukf8y";
-    let ht = match accumulator.get(hive_address) {
-      Some (amount) => amount.clone(),
-      None => BigDecimal::default(),
-    };
-    if ht.is_positive() {
-      accumulator.remove(hive_address);
-
-      let current = accumulator.entry(distr.developer_address().to_owned()).or_insert(BigDecimal::default());
-      *current += ht
-    }
-
-    let total_distributed = accumulator.values().fold(BigDecimal::default(), |acc, x| acc + x);
-    if total_distributed > epoch_info.tokens_for_epoch() {
-      panic!("Total distributed tokens > target tokens for epoch: {} > {}", total_distributed, epoch_info.tokens_for_epoch())
-    } else {
-      info!("Total distributed tokens: {} out of max of {}", total_distributed, epoch_info.tokens_for_epoch());
-    }
+    let accumulator = epoch_amounts(&distr, &epoch_info, &distribution, user_liquidity, &total_volume, user_volume);
+    info!("Total distributed tokens: {}", accumulator.values().sum::<BigDecimal>());
 
-    let leaves = Distribution::from(accumulator);
-    let tree = distribution::construct_merkle_tree(leaves);
+    let leaves = Distribution::from(accumulator, distr.hasher())
+      .map_err(|e| ApiError::Internal(e.to_string()))?;
+    let tree = distribution::construct_merkle_tree(leaves, distr.hasher());
     let proofs = distribution::get_proofs(tree.clone());
     let distributor_address = distr.distributor_address();
     let records: Vec<models::NewDistribution> = proofs.iter().map(|(d, p)| {
@@ -356,16 +929,13 @@ async fn generate_epoch(
       return Ok(String::from("Epoch already generated!"))
     }
 
-    for r in records.chunks(10000).into_iter() {
+    for r in records.chunks(10000) {
       db::insert_distributions(r.to_vec(), &conn).expect("Failed to insert distributions!");
     };
 
-    Ok::<String, diesel::result::Error>(encode(tree.root().data().clone().1))
+    Ok::<String, ApiError>(encode(tree.root().data().clone().1))
   })
-  .await.map_err(|e| {
-    eprintln!("{}", e);
-    HttpResponse::InternalServerError().finish()
-  })?;
+  .await?;
 
   Ok(HttpResponse::Ok().json(result))
 }
@@ -374,7 +944,7 @@ async fn generate_epoch(
 #[get("/distribution/info")]
 async fn get_distribution_info(
   distr_config: web::Data<DistributionConfigs>,
-) -> Result<HttpResponse, Error> {
+) -> Result<HttpResponse, ApiError> {
   Ok(HttpResponse::Ok().json(distr_config.get_ref()))
 }
 
@@ -390,140 +960,464 @@ async fn get_distribution_info(
 async fn get_distribution_amounts(
   pool: web::Data<DbPool>,
   distr_config: web::Data<DistributionConfigs>,
-  redis: web::Data<redis::Client>,
+  redis: web::Data<RedisPool>,
   web::Path(user_address): web::Path<String>,
-) -> Result<HttpResponse, Error> {
+) -> Result<HttpResponse, ApiError> {
   let result = web::block(move || {
-    let conn = pool.get().expect("couldn't get db connection from pool");
-    let mut rconn = redis.get_connection().expect("couldn't get redis connection");
+    let conn = get_conn(&pool)?;
+    let mut rconn = redis.get()?;
     let mut r: HashMap<String, HashMap<String, BigDecimal>> = HashMap::new();
 
     for distr in distr_config.iter() {
-      let mut accumulator: HashMap<String, BigDecimal> = HashMap::new();
-
       let epoch_info = EpochInfo::new(distr.emission(), None);
       let start = epoch_info.current_epoch_start();
       let end = epoch_info.current_epoch_end();
 
-      // get pool TWAL and individual TWAL
-      struct PoolDistribution {
-        tokens: BigDecimal,
-        weighted_liquidity: BigDecimal,
-      }
-      let pt = epoch_info.tokens_for_liquidity_providers();
-      let distribution: HashMap<String, PoolDistribution> =
-        if epoch_info.is_initial() {
-          let total_liquidity: BigDecimal = db::get_time_weighted_liquidity(&conn, &mut rconn, start, end, None)?.into_iter().map(|i| i.amount).sum();
-          db::get_pools(&conn)?.into_iter().map(|pool| {
-            (pool,
-              PoolDistribution{ // share distribution fully
-                tokens: utils::round_down(pt.clone(), 0),
-                weighted_liquidity: total_liquidity.clone(),
-              }
-            )
-          }).collect()
-        } else {
-          let pool_weights = distr.incentivized_pools();
-          let total_weight: u32 = pool_weights.values().into_iter().sum();
-          db::get_time_weighted_liquidity(&conn, &mut rconn, start, end, None)?.into_iter().filter_map(|i| {
-            if let Some(weight) = pool_weights.get(&i.pool) {
-              Some((i.pool,
-                PoolDistribution{ // each pool has a weighted allocation
-                  tokens: utils::round_down(pt.clone() * BigDecimal::from(*weight) / BigDecimal::from(total_weight), 0),
-                  weighted_liquidity: i.amount,
-                }
-              ))
-            } else {
-              None
-            }
-          }).collect()
-        };
-
-      // for each individual TWAL, calculate the tokens
+      let distribution = estimated_pool_distributions(&conn, &mut rconn, distr, &epoch_info)?;
       let user_liquidity = db::get_time_weighted_liquidity(&conn, &mut rconn, start, end, Some(&user_address))?;
-      for l in user_liquidity.into_iter() {
-        if let Some(pool) = distribution.get(&l.pool) {
-          let share = utils::round_down(l.amount * pool.tokens.clone() / pool.weighted_liquidity.clone(), 0);
-          let current = accumulator.entry(l.pool).or_insert(BigDecimal::default());
-          *current += share
+      let accumulator = estimated_amounts(distr, &epoch_info, &distribution, &user_address,
+        user_liquidity.into_iter().map(|l| (l.pool, l.amount)));
+
+      r.insert(distr.distributor_address().to_string(), accumulator);
+    }
+
+    Ok::<HashMap<String, HashMap<String, BigDecimal>>, ApiError>(r)
+  })
+  .await?;
+
+  Ok(HttpResponse::Ok().json(result))
+}
+
+/// Get the current estimated distribution amounts for each of the given user addresses,
+/// computing the pool allocations and time-weighted liquidity of all addresses once.
+#[post("/distribution/estimated_amounts")]
+async fn get_batch_distribution_amounts(
+  pool: web::Data<DbPool>,
+  distr_config: web::Data<DistributionConfigs>,
+  redis: web::Data<RedisPool>,
+  addresses: web::Json<Vec<String>>,
+) -> Result<HttpResponse, ApiError> {
+  if addresses.len() > MAX_ESTIMATE_ADDRESSES {
+    return Err(ApiError::BadRequest(format!("At most {} addresses can be estimated at once", MAX_ESTIMATE_ADDRESSES)))
+  }
+  let user_addresses = addresses.iter()
+    .map(|a| parse_address(a))
+    .collect::<Result<HashSet<String>, ApiError>>()?;
+
+  let result = web::block(move || {
+    let conn = get_conn(&pool)?;
+    let mut rconn = redis.get()?;
+    let mut r: HashMap<String, HashMap<String, HashMap<String, BigDecimal>>> = HashMap::new();
+
+    for distr in distr_config.iter() {
+      let epoch_info = EpochInfo::new(distr.emission(), None);
+      let start = epoch_info.current_epoch_start();
+      let end = epoch_info.current_epoch_end();
+
+      let distribution = estimated_pool_distributions(&conn, &mut rconn, distr, &epoch_info)?;
+      let mut user_liquidity: HashMap<String, Vec<(String, BigDecimal)>> = HashMap::new();
+      for l in db::get_time_weighted_liquidity_by_address(&conn, start, end)?.into_iter() {
+        if user_addresses.contains(&l.address) {
+          user_liquidity.entry(l.address).or_default().push((l.pool, l.amount));
         }
       }
 
-      // add developer share
-      if distr.developer_address() == user_address {
-        let current = accumulator.entry("developer".to_string()).or_insert(BigDecimal::default());
-        *current += epoch_info.tokens_for_developers()
+      for user_address in user_addresses.iter() {
+        let liquidity = user_liquidity.remove(user_address).unwrap_or_default();
+        let accumulator = estimated_amounts(distr, &epoch_info, &distribution, user_address, liquidity);
+        r.entry(user_address.clone()).or_default()
+          .insert(distr.distributor_address().to_string(), accumulator);
       }
-
-      r.insert(distr.distributor_address().to_string(), accumulator);
     }
 
-    Ok::<HashMap<String, HashMap<String, BigDecimal>>, diesel::result::Error>(r)
+    Ok::<HashMap<String, HashMap<String, HashMap<String, BigDecimal>>>, ApiError>(r)
   })
-  .await.map_err(|e| {
-    eprintln!("{}", e);
-    HttpResponse::InternalServerError().finish()
-  })?;
+  .await?;
 
   Ok(HttpResponse::Ok().json(result))
 }
 
+/// Liquidity provider tokens of a pool in an epoch, to be shared by time-weighted liquidity.
+struct PoolDistribution {
+  tokens: BigDecimal,
+  weighted_liquidity: BigDecimal,
+}
+
+/// Splits the tokens of an epoch between liquidity providers by their time-weighted liquidity
+/// in each pool, traders by their volume and developers. Rewards of redirected addresses are
+/// given to the address they are redirected to, and the tokens left over from rounding down
+/// each share to the remainder address.
+fn epoch_amounts(
+  distr: &DistributionConfig,
+  epoch_info: &EpochInfo,
+  distribution: &HashMap<String, PoolDistribution>,
+  user_liquidity: Vec<models::LiquidityFromProvider>,
+  total_volume: &BigDecimal,
+  user_volume: Vec<models::VolumeForUser>,
+) -> HashMap<String, BigDecimal> {
+  let mut accumulator = EpochAmounts::default();
+
+  // for each individual TWAL, calculate the tokens
+  for l in user_liquidity.into_iter() {
+    if let Some(pool) = distribution.get(&l.pool) {
+      accumulator.add(l.address, l.amount * pool.tokens.clone() / pool.weighted_liquidity.clone());
+    }
+  }
+
+  // add distr for swap volumes
+  let tt = epoch_info.tokens_for_traders();
+  if tt.is_positive() && total_volume.is_positive() {
+    for v in user_volume.into_iter() {
+      accumulator.add(v.address, tt.clone() * v.amount / total_volume.clone());
+    }
+  }
+
+  // add developer share
+  let dt = epoch_info.tokens_for_developers();
+  if dt.is_positive() {
+    for (address, share) in distr.developer_shares(&dt).into_iter() {
+      accumulator.add(address, share);
+    }
+  }
+
+  // redirect rewards of configured addresses, e.g. of contracts holding liquidity
+  for (from, to) in distr.redirect_addresses().iter() {
+    if let Some(rt) = accumulator.redirect(from, to) {
+      info!("Redirecting {} tokens from {} to {}", rt, from, to);
+    }
+  }
+
+  // give what is left from rounding down each share to the developer, so that the epoch is fully distributed
+  let tokens_for_epoch = utils::round_down(epoch_info.tokens_for_epoch(), 0);
+  let address = distr.remainder_address();
+  let remainder = accumulator.add_dust(address.clone(), &tokens_for_epoch);
+  if remainder.is_positive() {
+    info!("Giving remaining {} tokens to {}", remainder, address);
+  }
+
+  let total_distributed = accumulator.total();
+  if total_distributed > tokens_for_epoch {
+    panic!("Total distributed tokens > target tokens for epoch: {} > {}", total_distributed, tokens_for_epoch)
+  }
+  accumulator.into_amounts()
+}
+
+/// Gets the liquidity provider tokens of each pool in the current epoch of a distribution.
+fn estimated_pool_distributions(
+  conn: &PgConnection,
+  rconn: &mut redis::Connection,
+  distr: &DistributionConfig,
+  epoch_info: &EpochInfo,
+) -> Result<HashMap<String, PoolDistribution>, ApiError> {
+  let start = epoch_info.current_epoch_start();
+  let end = epoch_info.current_epoch_end();
+
+  let pt = epoch_info.tokens_for_liquidity_providers();
+  let mut distribution: HashMap<String, PoolDistribution> =
+    if epoch_info.is_initial() {
+      let total_liquidity: BigDecimal = db::get_time_weighted_liquidity(conn, rconn, start, end, None)?.into_iter().map(|i| i.amount).sum();
+      db::get_pools(conn)?.into_iter().map(|pool| {
+        (pool,
+          PoolDistribution{ // share distribution fully
+            tokens: pt.clone(),
+            weighted_liquidity: total_liquidity.clone(),
+          }
+        )
+      }).collect()
+    } else {
+      let pool_weights = distr.incentivized_pools();
+      let total_weight: u32 = pool_weights.values().sum();
+      db::get_time_weighted_liquidity(conn, rconn, start, end, None)?.into_iter().filter_map(|i| {
+        if let Some(weight) = pool_weights.get(&i.pool) {
+          Some((i.pool,
+            PoolDistribution{ // each pool has a weighted allocation
+              tokens: pt.clone() * BigDecimal::from(*weight) / BigDecimal::from(total_weight),
+              weighted_liquidity: i.amount,
+            }
+          ))
+        } else {
+          None
+        }
+      }).collect()
+    };
+
+  // pools without weighted liquidity have no one to share their tokens with
+  distribution.retain(|_, d| d.weighted_liquidity.is_positive());
+
+  Ok(distribution)
+}
+
+/// Estimates the amounts of a user address by pool, from its time-weighted liquidity in each
+/// pool, and as `developer` if it receives a developer share.
+fn estimated_amounts(
+  distr: &DistributionConfig,
+  epoch_info: &EpochInfo,
+  distribution: &HashMap<String, PoolDistribution>,
+  user_address: &str,
+  user_liquidity: impl IntoIterator<Item = (String, BigDecimal)>,
+) -> HashMap<String, BigDecimal> {
+  let mut accumulator: HashMap<String, BigDecimal> = HashMap::new();
+
+  // for each individual TWAL, calculate the tokens
+  for (pool_address, amount) in user_liquidity.into_iter() {
+    if let Some(pool) = distribution.get(&pool_address) {
+      let share = utils::round_down(amount * pool.tokens.clone() / pool.weighted_liquidity.clone(), 0);
+      let current = accumulator.entry(pool_address).or_default();
+      *current += share
+    }
+  }
+
+  // add developer share
+  for (address, share) in distr.developer_shares(&epoch_info.tokens_for_developers()).into_iter() {
+    if address == user_address {
+      let current = accumulator.entry("developer".to_string()).or_default();
+      *current += utils::round_down(share, 0)
+    }
+  }
+
+  accumulator
+}
+
 /// Get distribution data by epoch.
 #[get("/distribution/data/{distributor_address}/{epoch_number}")]
 async fn get_distribution_data(
   pool: web::Data<DbPool>,
+  pagination: web::Query<PaginationInfo>,
   filter: web::Query<AddressInfo>,
   web::Path((distributor_address, epoch_number)): web::Path<(String, i32)>,
-) -> Result<HttpResponse, Error> {
+) -> Result<HttpResponse, ApiError> {
+  let distributor_address = parse_distributor_address(&distributor_address)?;
+  let address = address_filter(filter.address.as_deref())?;
   let distributions = web::block(move || {
-    let conn = pool.get().expect("couldn't get db connection from pool");
-    db::get_distributions(&conn, Some(&distributor_address), Some(epoch_number), filter.address.as_deref())
+    let conn = get_conn(&pool)?;
+    if !db::epoch_exists(&conn, &distributor_address, &epoch_number)? {
+      return Err(ApiError::NotFound(format!("No distribution for epoch {} of {}", epoch_number, distributor_address)))
+    }
+    Ok(db::get_distributions(&conn, Some(&distributor_address), Some(epoch_number), address.as_deref(), pagination.per_page, pagination.page)?)
   })
-  .await.map_err(|e| {
-    eprintln!("{}", e);
-    HttpResponse::InternalServerError().finish()
-  })?;
+  .await?;
 
   Ok(HttpResponse::Ok().json(distributions))
 }
 
+/// Get the distribution of an address for an epoch, with its proof.
+#[get("/distribution/proof/{distributor_address}/{epoch_number}/{user_address}")]
+async fn get_distribution_proof(
+  pool: web::Data<DbPool>,
+  web::Path((distributor_address, epoch_number, user_address)): web::Path<(String, i32, String)>,
+) -> Result<HttpResponse, ApiError> {
+  let distributor_address = parse_distributor_address(&distributor_address)?;
+  let user_address = parse_address(&user_address)?;
+  let distribution = web::block(move || {
+    let conn = get_conn(&pool)?;
+    match db::get_distribution(&conn, &distributor_address, epoch_number, &user_address)? {
+      Some(distribution) => Ok(distribution),
+      None => Err(ApiError::NotFound(format!("No distribution for {} in epoch {} of {}", user_address, epoch_number, distributor_address))),
+    }
+  })
+  .await?;
+
+  Ok(HttpResponse::Ok().json(distribution))
+}
+
+/// Verify a merkle proof for a distribution against the epoch's stored merkle root.
+#[get("/distribution/verify")]
+async fn verify_distribution_proof(
+  pool: web::Data<DbPool>,
+  distr_config: web::Data<DistributionConfigs>,
+  query: web::Query<ProofInfo>,
+) -> Result<HttpResponse, ApiError> {
+  let distr_address = parse_distributor_address(&query.distr_address)?;
+  let distr = distr_config.iter().find(|d| d.distributor_address() == distr_address)
+    .ok_or(ApiError::NotFound(format!("No distribution for {}", distr_address)))?
+    .clone();
+  let address = parse_address(&query.address)?;
+  let leaf = Distribution::new(address, query.amount.clone(), distr.hasher())
+    .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+
+  let result = web::block(move || {
+    let conn = get_conn(&pool)?;
+    let root = db::get_merkle_root(&conn, &distr_address, query.epoch_number)?
+      .ok_or(ApiError::NotFound(format!("No distribution for epoch {} of {}", query.epoch_number, distr_address)))?;
+    let root_bytes = hex::decode(&root).map_err(|e| ApiError::Internal(e.to_string()))?;
+    let valid = distribution::verify_proof(&leaf, &query.proof, &root_bytes, distr.hasher());
+    Ok::<models::ProofVerification, ApiError>(models::ProofVerification { valid, root })
+  })
+  .await?;
+
+  Ok(HttpResponse::Ok().json(result))
+}
+
 /// Get distribution data for claimable (and unclaimed) epochs by user address.
 #[get("/distribution/claimable_data/{user_address}")]
 async fn get_distribution_data_by_address(
   pool: web::Data<DbPool>,
   web::Path(user_address): web::Path<String>,
-) -> Result<HttpResponse, Error> {
+) -> Result<HttpResponse, ApiError> {
+  let user_address = parse_address(&user_address)?;
   let distributions = web::block(move || {
-    let conn = pool.get().expect("couldn't get db connection from pool");
-    db::get_unclaimed_distributions_by_address(&conn, &user_address)
+    let conn = get_conn(&pool)?;
+    db::get_unclaimed_distributions_by_address(&conn, &user_address).map_err(ApiError::from)
   })
-  .await.map_err(|e| {
-    eprintln!("{}", e);
-    HttpResponse::InternalServerError().finish()
-  })?;
+  .await?;
 
   Ok(HttpResponse::Ok().json(distributions))
 }
 
-/// Get claims history.
+/// Get the amounts claimed and still claimable by user address for each distributor.
+#[get("/distribution/summary/{user_address}")]
+async fn get_distribution_summary_by_address(
+  pool: web::Data<DbPool>,
+  web::Path(user_address): web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+  let user_address = parse_address(&user_address)?;
+  let summary = web::block(move || {
+    let conn = get_conn(&pool)?;
+    db::get_distribution_summary_by_address(&conn, &user_address).map_err(ApiError::from)
+  })
+  .await?;
+
+  Ok(HttpResponse::Ok().json(summary))
+}
+
+/// Get claims history, by epoch by default or sorted with `sort=epoch_number|block_timestamp` and `order=asc|desc`.
 #[get("/claims")]
 async fn get_claims(
   pagination: web::Query<PaginationInfo>,
   filter: web::Query<ClaimInfo>,
+  period: web::Query<PeriodInfo>,
+  blocks: web::Query<BlockRangeInfo>,
   pool: web::Data<DbPool>,
-) -> Result<HttpResponse, Error> {
+) -> Result<HttpResponse, ApiError> {
+  let address = address_filter(filter.address.as_deref())?;
+  let distr_address = filter.distr_address.as_deref().map(parse_distributor_address).transpose()?;
+  let block_range = blocks.range()?;
   let claims = web::block(move || {
-    let conn = pool.get().expect("couldn't get db connection from pool");
-    db::get_claims(&conn, filter.address.as_deref(), filter.distr_address.as_deref(), filter.epoch_number.as_ref(), pagination.per_page, pagination.page)
+    let conn = get_conn(&pool)?;
+    db::get_claims(&conn, address.as_deref(), distr_address.as_deref(), filter.epoch_number.as_ref(), period.from, period.until, block_range, filter.sort, filter.order, pagination.per_page, pagination.page).map_err(ApiError::from)
   })
-  .await.map_err(|e| {
-    eprintln!("{}", e);
-    HttpResponse::InternalServerError().finish()
-  })?;
+  .await?;
 
   Ok(HttpResponse::Ok().json(claims))
 }
 
+/// Checks out a db connection, recording how long the checkout took.
+/// Fails with a 503 if no connection is available before the pool's timeout.
+fn get_conn(pool: &DbPool) -> Result<r2d2::PooledConnection<ConnectionManager<PgConnection>>, ApiError> {
+  let timer = metrics::DB_POOL_CHECKOUT_SECONDS.start_timer();
+  let conn = pool.get().map_err(|e| {
+    error!("{}", e);
+    ApiError::ServiceUnavailable(String::from("database unavailable"))
+  });
+  timer.observe_duration();
+  conn
+}
+
+/// Checks that the request carries a bearer token matching the api key in the given env var.
+/// Requests are always rejected if the env var is not set.
+fn authorize(req: &HttpRequest, key_var: &str) -> Result<(), ApiError> {
+  let api_key = std::env::var(key_var).unwrap_or(String::from(""));
+  let token = req.headers().get("Authorization")
+    .and_then(|h| h.to_str().ok())
+    .and_then(|h| h.strip_prefix("Bearer "))
+    .unwrap_or("");
+
+  if api_key.is_empty() || constant_time::verify_slices_are_equal(api_key.as_bytes(), token.as_bytes()).is_err() {
+    return Err(ApiError::Unauthorized(String::from("Missing or invalid api key")))
+  }
+  Ok(())
+}
+
+/// Gets the usd price of a zil, cached for `PRICE_CACHE_TTL_SECS`.
+/// Returns None if the price oracle cannot be reached.
+async fn get_zil_usd_price(redis: &web::Data<RedisPool>, oracle: &PriceOracle) -> Option<BigDecimal> {
+  let cache = redis.clone();
+  let cached = web::block(move || {
+    let mut rconn = cache.get()?;
+    let cache_value: Option<String> = rconn.get(PRICE_CACHE_KEY).unwrap_or(None);
+    Ok::<Option<BigDecimal>, ApiError>(cache_value.and_then(|v| BigDecimal::from_str(&v).ok()))
+  })
+  .await
+  .unwrap_or(None);
+  if cached.is_some() {
+    return cached
+  }
+
+  let price = match oracle.get_zil_usd_price().await {
+    Ok(price) => price,
+    Err(e) => {
+      warn!("Failed to fetch zil price: {}", e);
+      return None
+    }
+  };
+
+  let cache = redis.clone();
+  let cache_value = price.to_string();
+  let cached = web::block(move || {
+    let mut rconn = cache.get()?;
+    if let Err(e) = rconn.set_ex::<&str, String, ()>(PRICE_CACHE_KEY, cache_value, PRICE_CACHE_TTL_SECS) {
+      error!("{}", e)
+    }
+    Ok::<(), ApiError>(())
+  })
+  .await;
+  if let Err(e) = cached {
+    error!("{}", e)
+  }
+
+  Some(price)
+}
+
+/// Gets the current reserves of all pools from the zilswap contract. If the node cannot
+/// be reached, the reserves from the latest indexed `PoolSynced` events are used.
+async fn get_pool_reserves(
+  pool: &web::Data<DbPool>,
+  zil_client: &rpc::AsyncZilliqaClient,
+  worker_config: &WorkerConfig,
+) -> Result<Vec<rpc::PoolReserves>, ApiError> {
+  match zil_client.get_pool_reserves(worker_config.contract_address()).await {
+    Ok(reserves) => Ok(reserves),
+    Err(e) => {
+      warn!("Failed to fetch pool reserves, using indexed reserves: {}", e);
+      let pool = pool.clone();
+      web::block(move || {
+        let conn = get_conn(&pool)?;
+        let reserves = db::get_latest_reserves(&conn)?.into_iter().map(|r| rpc::PoolReserves {
+          token_address: r.token_address,
+          zil_reserve: r.zil_reserve,
+          token_reserve: r.token_reserve,
+          fee_rate: None,
+        }).collect();
+        Ok::<Vec<rpc::PoolReserves>, ApiError>(reserves)
+      })
+      .await
+      .map_err(ApiError::from)
+    }
+  }
+}
+
+/// Normalizes a bech32 or hex address to the stored bech32 form, rejecting invalid addresses.
+fn parse_address(address: &str) -> Result<String, ApiError> {
+  utils::normalize_address(address).ok_or(ApiError::BadRequest(format!("Invalid address {}: expected a bech32 (zil1...) or hex (0x...) address", address)))
+}
+
+/// Normalizes a bech32 or hex distributor address to the stored hex form, rejecting invalid addresses.
+fn parse_distributor_address(address: &str) -> Result<String, ApiError> {
+  utils::normalize_hex_address(address).ok_or(ApiError::BadRequest(format!("Invalid distributor address {}: expected a bech32 (zil1...) or hex (0x...) address", address)))
+}
+
+/// Normalizes an optional address filter, see `parse_address`.
+fn address_filter(address: Option<&str>) -> Result<Option<String>, ApiError> {
+  address.map(parse_address).transpose()
+}
+
+/// Normalizes an optional filter of comma-separated pools, see `parse_address`.
+fn pools_filter(pools: Option<&str>) -> Result<Option<String>, ApiError> {
+  pools.map(|p| {
+    p.split(',').map(parse_address).collect::<Result<Vec<String>, ApiError>>().map(|p| p.join(","))
+  }).transpose()
+}
+
 fn var_enabled(var_str: &str) -> bool {
   let run = std::env::var(var_str).unwrap_or(String::from("false"));
   if run == "true" || run == "t" || run == "1" {
@@ -532,6 +1426,21 @@ fn var_enabled(var_str: &str) -> bool {
   false
 }
 
+/// Reads the section of the given network from the config file, with its distributions,
+/// which are validated.
+fn load_config(path: &str, network: &Network) -> std::io::Result<(serde_yaml::Value, DistributionConfigs)> {
+  let f = std::fs::File::open(path)?;
+  let data: serde_yaml::Value = serde_yaml::from_reader(f).expect("Could not read config.yml");
+  let config = data[network.to_string()].clone();
+  let distr_configs = serde_yaml::from_value::<DistributionConfigs>(
+    config["distributions"].clone()
+  ).expect("Failed to parse distributions in config.yml");
+  if let Err(e) = distr_configs.validate() {
+    panic!("Error in config.yml: {:#?}", e);
+  }
+  Ok((config, distr_configs))
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
   let env_path = std::env::var("ENV_FILE").unwrap_or(String::from("./.env"));
@@ -546,40 +1455,62 @@ async fn main() -> std::io::Result<()> {
     .build(manager)
     .expect("Failed to create db pool.");
 
-  // set up redis connection
+  // set up redis connection pool
   let rconnspec = std::env::var("REDIS_URL").unwrap_or(String::from("redis://127.0.0.1/"));
-  // let rmanager = redis::ConnectionManager::<PgConnection>::new(connspec);
-  let redis = redis::Client::open(rconnspec).expect("Could not connect to redis");
-  let mut con = redis.get_connection().expect("Failed to get redis connection");
+  let redis_pool_size = std::env::var("REDIS_POOL_SIZE").unwrap_or(String::from("15")).parse::<u32>().expect("invalid env value for REDIS_POOL_SIZE");
+  let rmanager = redis::Client::open(rconnspec).expect("Could not connect to redis");
+  let redis = r2d2::Pool::builder()
+    .max_size(redis_pool_size)
+    .build(rmanager)
+    .expect("Failed to create redis pool.");
+  let mut con = redis.get().expect("Failed to get redis connection");
   // throw away the result, just make sure it does not fail
   let _ : () = con.set("zap-api-redis:test", 42).expect("Failed to set value on redis");
 
   // get network
   let network_str = std::env::var("NETWORK").unwrap_or(String::from("testnet"));
-  let network = match network_str.as_str() {
-    "testnet" => Network::TestNet,
-    "mainnet" => Network::MainNet,
-    _ => panic!("Invalid network string")
-  };
+  let network = Network::from_str(&network_str).expect("invalid env value for NETWORK");
 
   // load config
+  let min_epoch_period = std::env::var("MIN_EPOCH_PERIOD").unwrap_or(String::from("3600")).parse::<i64>().expect("invalid env value for MIN_EPOCH_PERIOD");
+  distribution::configure_min_epoch_period(min_epoch_period);
   let config_file_path = std::env::var("CONFIG_FILE").unwrap_or(String::from("config/config.yml"));
-  let f = std::fs::File::open(config_file_path)?;
-  let data: serde_yaml::Value = serde_yaml::from_reader(f).expect("Could not read config.yml");
-  let config = data[network.to_string()].clone();
-  let distr_configs = serde_yaml::from_value::<DistributionConfigs>(
-    config["distributions"].clone()
-  ).expect("Failed to parse distributions in config.yml");
-  if let Err(e) = distr_configs.validate() {
-    panic!("Error in config.yml: {:#?}", e);
-  }
+  let (config, distr_configs) = load_config(&config_file_path, &network)?;
+  let quote_allowlist = serde_yaml::from_value::<Option<Vec<String>>>(config["quote_allowlist"].clone())
+    .expect("Failed to parse quote_allowlist in config.yml")
+    .unwrap_or_default();
+  let token_allowlist = TokenAllowlist::new(&quote_allowlist)
+    .unwrap_or_else(|address| panic!("Error in config.yml: invalid address {} in quote_allowlist", address));
 
   // worker config
   let contract_hash = serde_yaml::from_value::<String>(config["zilswap_address_hex"].clone()).expect("invalid zilswap_address_hex");
   let distributor_contract_hashes = distr_configs.iter().map(|d| d.distributor_address()).collect();
   let min_sync_height: u32 = serde_yaml::from_value(config["zilswap_min_sync_at"].clone()).expect("invalid zilswap_min_sync_at");
-  let rpc_url = std::env::var("RPC_URL").unwrap_or("https://api.zilliqa.com".to_string());
-  let worker_config = WorkerConfig::new(network, contract_hash.as_str(), distributor_contract_hashes, min_sync_height, rpc_url);
+  let rpc_urls = std::env::var("RPC_URL").unwrap_or("https://api.zilliqa.com".to_string())
+    .split(',')
+    .map(|url| url.trim().to_string())
+    .filter(|url| !url.is_empty())
+    .collect::<Vec<String>>();
+  if rpc_urls.is_empty() {
+    panic!("invalid env value for RPC_URL");
+  }
+  let rpc_timeout = std::env::var("RPC_TIMEOUT_SECS").unwrap_or(String::from("30")).parse::<u64>().expect("invalid env value for RPC_TIMEOUT_SECS");
+  let confirmation_depth = std::env::var("CONFIRMATION_DEPTH").unwrap_or(String::from("0")).parse::<u32>().expect("invalid env value for CONFIRMATION_DEPTH");
+  let worker_threads = std::env::var("WORKER_THREADS").unwrap_or(String::from("5")).parse::<usize>().expect("invalid env value for WORKER_THREADS");
+  let batch_size = std::env::var("WORKER_BATCH_SIZE").unwrap_or(String::from("1")).parse::<u32>().expect("invalid env value for WORKER_BATCH_SIZE");
+  // async rpc client for handlers that query the node
+  let zil_client = rpc::AsyncZilliqaClient::new(&rpc_urls, Duration::from_secs(rpc_timeout));
+
+  // zil price in usd, for denominating volumes
+  let price_oracle_url = std::env::var("PRICE_ORACLE_URL").unwrap_or(String::from("https://api.coingecko.com/api/v3/simple/price?ids=zilliqa&vs_currencies=usd"));
+  let price_oracle = PriceOracle::new(&price_oracle_url, Duration::from_secs(rpc_timeout));
+
+  // token metadata, refreshed in the background
+  let zilstream_url = std::env::var("ZILSTREAM_URL").unwrap_or(String::from("https://api.zilstream.com/tokens"));
+  let token_list = TokenList::default();
+  token_list.start_refresh(zilstream_url, Duration::from_secs(rpc_timeout));
+
+  let worker_config = WorkerConfig::new(network, contract_hash.as_str(), distributor_contract_hashes, min_sync_height, rpc_urls, Duration::from_secs(rpc_timeout), confirmation_depth, worker_threads, batch_size);
 
   // get number of threads to run
   let threads_str = std::env::var("SERVER_THREADS").unwrap_or(String::from(""));
@@ -594,18 +1525,96 @@ async fn main() -> std::io::Result<()> {
   }
 
   // run worker
-  if var_enabled("RUN_WORKER") {
+  let coordinator = if var_enabled("RUN_WORKER") {
     info!("Running worker..");
-    let _addr = worker::Coordinator::new(worker_config, pool.clone()).start();
+    Some(worker::Coordinator::new(worker_config.clone(), pool.clone(), redis.clone()).start())
+  } else {
+    None
+  };
+
+  let coordinator_data = web::Data::new(coordinator.clone());
+  let worker_pool = pool.clone();
+
+  // serve metrics on a separate address if given, otherwise alongside the api
+  let metrics_bind = std::env::var("METRICS_BIND").ok();
+  if let Some(metrics_bind) = &metrics_bind {
+    info!("Starting metrics server at {}", metrics_bind);
+    HttpServer::new(|| App::new().service(metrics::get_metrics))
+      .workers(1)
+      .bind(metrics_bind)?
+      .run();
   }
+  let serve_metrics = metrics_bind.is_none();
+
+  // configure pagination
+  let default_per_page = std::env::var("DEFAULT_PER_PAGE").unwrap_or(String::from("10")).parse::<i64>().expect("invalid env value for DEFAULT_PER_PAGE");
+  let max_per_page = std::env::var("MAX_PER_PAGE").unwrap_or(String::from("50")).parse::<i64>().expect("invalid env value for MAX_PER_PAGE");
+  pagination::configure(default_per_page, max_per_page);
+
+  let wash_trade_window = std::env::var("WASH_TRADE_WINDOW").unwrap_or(String::from("300")).parse::<i64>().expect("invalid env value for WASH_TRADE_WINDOW");
+  db::configure_wash_trade_window(wash_trade_window);
+  let cache_ttl = std::env::var("CACHE_TTL_SECS").unwrap_or(String::from("60")).parse::<usize>().expect("invalid env value for CACHE_TTL_SECS");
+  let historical_cache_ttl = std::env::var("HISTORICAL_CACHE_TTL_SECS").unwrap_or(String::from("21600")).parse::<usize>().expect("invalid env value for HISTORICAL_CACHE_TTL_SECS");
+  db::configure_cache_ttl(cache_ttl, historical_cache_ttl);
+  if var_enabled("NOCACHE") {
+    warn!("Caching is disabled.");
+    db::disable_cache();
+  }
+
+  // limit concurrent epoch generations
+  let max_generations = std::env::var("MAX_CONCURRENT_GENERATIONS").unwrap_or(String::from("1")).parse::<usize>().expect("invalid env value for MAX_CONCURRENT_GENERATIONS");
+  let generate_limit = web::Data::new(GenerateLimit::new(max_generations));
+
+  // limit request body sizes
+  let max_body_size = std::env::var("MAX_BODY_SIZE").unwrap_or(String::from("262144")).parse::<usize>().expect("invalid env value for MAX_BODY_SIZE");
 
   let bind = std::env::var("BIND").or(Ok::<String, Error>(String::from("127.0.0.1:3000"))).unwrap();
   let mut server = HttpServer::new(move || {
-    App::new()
-      .wrap(Logger::default())
+    let app = App::new()
+      // compress responses as negotiated with accept-encoding
+      .wrap(Compress::default())
+      // tag each request with an id, and log it as a json line
+      .wrap_fn(|req, srv| {
+        let start = Instant::now();
+        let request_id = uuid::Uuid::from_random_bytes(rand::random()).to_string();
+        let method = req.method().to_string();
+        let path = req.path().to_owned();
+        let remote_addr = req.connection_info().realip_remote_addr().map(String::from);
+        let route = req.match_pattern();
+        srv.call(req).map(move |res| {
+          res.map(|mut res| {
+            let status = res.status().as_u16();
+            let elapsed = start.elapsed();
+            metrics::observe_request(&method, route, status, elapsed);
+            info!("{}", serde_json::json!({
+              "request_id": request_id,
+              "method": method,
+              "path": path,
+              "status": status,
+              "latency_ms": elapsed.as_secs_f64() * 1000.0,
+              "remote_addr": remote_addr,
+            }));
+            if let Ok(value) = HeaderValue::from_str(&request_id) {
+              res.headers_mut().insert(HeaderName::from_static("x-request-id"), value);
+            }
+            res
+          })
+        })
+      })
       .data(pool.clone())
       .data(distr_configs.clone())
       .data(redis.clone())
+      .data(worker_config.clone())
+      .data(zil_client.clone())
+      .data(token_list.clone())
+      .data(token_allowlist.clone())
+      .data(price_oracle.clone())
+      .app_data(generate_limit.clone())
+      .app_data(coordinator_data.clone())
+      .app_data(web::QueryConfig::default().error_handler(|err, _| ApiError::BadRequest(err.to_string()).into()))
+      .app_data(web::PathConfig::default().error_handler(|err, _| ApiError::BadRequest(err.to_string()).into()))
+      .app_data(web::JsonConfig::default().limit(max_body_size).error_handler(|err, _| ApiError::from(err).into()))
+      .app_data(web::PayloadConfig::new(max_body_size))
       .wrap(Cors::default()
         .max_age(Some(3600))
         .expose_any_header()
@@ -618,14 +1627,38 @@ async fn main() -> std::io::Result<()> {
       .service(get_claims)
       .service(get_distribution_info)
       .service(get_distribution_amounts)
+      .service(get_batch_distribution_amounts)
       .service(get_distribution_data)
+      .service(get_distribution_proof)
       .service(get_distribution_data_by_address)
+      .service(get_distribution_summary_by_address)
+      .service(verify_distribution_proof)
       .service(get_swaps)
       .service(get_volume)
+      .service(get_volume_by_address)
+      .service(get_coingecko_tickers)
       .service(get_transactions)
       .service(get_liquidity_changes)
       .service(get_liquidity)
-      .service(get_weighted_liquidity)
+      .service(get_liquidity_flow)
+      .service(get_pool_lifespan)
+      .service(get_pool_share)
+      .service(get_pools)
+      .service(get_tokens)
+      .service(get_quote)
+      .service(get_lp_counts)
+      .service(get_worker_status)
+      .service(get_sync_status)
+      .service(get_failed_events)
+      .service(reprocess_failed_event)
+      .service(backfill)
+      .service(get_weighted_liquidity);
+
+    if serve_metrics {
+      app.service(metrics::get_metrics)
+    } else {
+      app
+    }
   });
 
   if let Ok(threads) = threads_str.parse::<usize>() {
@@ -636,7 +1669,129 @@ async fn main() -> std::io::Result<()> {
   }
   info!("Starting server at {}", &bind);
 
+  // the server stops gracefully on SIGINT / SIGTERM, after which the worker is stopped
   server.bind(bind)?
     .run()
-    .await
+    .await?;
+
+  if let Some(coordinator) = coordinator {
+    let shutdown_timeout = std::env::var("WORKER_SHUTDOWN_TIMEOUT").unwrap_or(String::from("30")).parse::<u64>().expect("invalid env value for WORKER_SHUTDOWN_TIMEOUT");
+    worker::shutdown(coordinator, worker_pool, Duration::from_secs(shutdown_timeout)).await;
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use actix_web::test;
+  use actix_web::http::StatusCode;
+
+  #[test]
+  fn test_load_config() {
+    for network in [Network::MainNet, Network::TestNet] {
+      let (_, distr_configs) = load_config("config/config.yml", &network).unwrap();
+      assert!(!distr_configs.is_empty());
+    }
+
+    // a local node has no distributor contracts
+    let (config, distr_configs) = load_config("config/config.yml", &Network::LocalHost).unwrap();
+    assert!(distr_configs.is_empty());
+    assert_eq!(config["zilswap_min_sync_at"], 0);
+  }
+
+  #[test]
+  fn test_generate_limit() {
+    let limit = GenerateLimit::new(1);
+    let permit = limit.try_acquire();
+    assert!(permit.is_some());
+    assert!(limit.try_acquire().is_none());
+
+    drop(permit);
+    assert!(limit.try_acquire().is_some());
+  }
+
+  #[test]
+  fn test_epoch_amounts_total_tokens_for_epoch() {
+    let developer = test_utils::random_address();
+    let (pool_a, pool_b, pool_c) = (test_utils::random_address(), test_utils::random_address(), test_utils::random_address());
+    let distr: DistributionConfig = serde_json::from_value(serde_json::json!({
+      "name": "ZWAP Rewards",
+      "reward_token_symbol": "ZWAP",
+      "reward_token_address_hex": "0x0d21c1901a06abee40d8177f95171c8c63abdc31",
+      "distributor_name": "ZWAP Distributor",
+      "distributor_address_hex": "0x459cb2d3baf7e61cfbd5fe362f289ae92b2babb0",
+      "developer_address": developer,
+      "emission_info": {
+        "epoch_period": 604800,
+        "tokens_per_epoch": "1000000",
+        "tokens_for_retroactive_distribution": "0",
+        "retroactive_distribution_cutoff_time": 0,
+        "distribution_start_time": 1600000000,
+        "total_number_of_epochs": 10,
+        "initial_epoch_number": 1,
+        "developer_token_ratio_bps": 1500,
+        "trader_token_ratio_bps": 0,
+      },
+      "incentivized_pools": { pool_a.clone(): 1, pool_b.clone(): 2, pool_c.clone(): 3 },
+    })).unwrap();
+    let epoch_info = EpochInfo::new(distr.emission(), Some(1));
+    assert!(!epoch_info.is_initial());
+
+    // three providers with equal liquidity in each pool, so that no share is a whole number of tokens
+    let pool_distribution = |weight: u32| PoolDistribution {
+      tokens: epoch_info.tokens_for_liquidity_providers() * BigDecimal::from(weight) / BigDecimal::from(6),
+      weighted_liquidity: BigDecimal::from(3),
+    };
+    let mut distribution = HashMap::new();
+    distribution.insert(pool_a.clone(), pool_distribution(1));
+    distribution.insert(pool_b.clone(), pool_distribution(2));
+    distribution.insert(pool_c.clone(), pool_distribution(3));
+    let user_liquidity = |pools: &[&String]| -> Vec<models::LiquidityFromProvider> {
+      pools.iter().flat_map(|pool| (0..3).map(move |_| models::LiquidityFromProvider {
+        pool: pool.to_string(),
+        address: test_utils::random_address(),
+        amount: BigDecimal::from(1),
+      })).collect()
+    };
+
+    let amounts = epoch_amounts(&distr, &epoch_info, &distribution, user_liquidity(&[&pool_a, &pool_b, &pool_c]), &BigDecimal::default(), vec![]);
+    assert_eq!(amounts.values().sum::<BigDecimal>(), epoch_info.tokens_for_epoch());
+    // 150000 for developers, and 4 tokens of dust from shares of 850000 / 18, 2 * 850000 / 18 and 3 * 850000 / 18
+    assert_eq!(amounts[&developer], BigDecimal::from(150_004));
+
+    // tokens of a pool without providers are not given out as dust
+    let amounts = epoch_amounts(&distr, &epoch_info, &distribution, user_liquidity(&[&pool_a, &pool_b]), &BigDecimal::default(), vec![]);
+    assert_eq!(amounts.values().sum::<BigDecimal>(), BigDecimal::from(575_000));
+    assert_eq!(amounts[&developer], BigDecimal::from(150_002));
+  }
+
+  #[actix_rt::test]
+  async fn test_quote_rejects_tokens_not_on_allowlist() {
+    let allowed = "zil1p5suryq6q647usxczale29cu3336hhp376c627";
+    let other = "zil1z5l74hwy3pc3pr3gdh3nqju4jlyp0dzkhq2f5y";
+    let rpc_urls = vec![String::from("http://127.0.0.1:4201")];
+    let worker_config = WorkerConfig::new(Network::LocalHost, "0x459cb2d3baf7e61cfbd5fe362f289ae92b2babb0", vec![], 0, rpc_urls.clone(), Duration::from_secs(1), 0, 1, 1);
+    // neither the database nor the node is reached, as the quote is rejected first
+    let db_pool: DbPool = r2d2::Pool::builder().build_unchecked(ConnectionManager::new("postgres://127.0.0.1/unused"));
+    let mut app = test::init_service(App::new()
+      .data(db_pool)
+      .data(rpc::AsyncZilliqaClient::new(&rpc_urls, Duration::from_secs(1)))
+      .data(worker_config)
+      .data(TokenAllowlist::new(&[String::from(allowed)]).unwrap())
+      .service(get_quote)
+    ).await;
+
+    let uris = vec![
+      format!("/quote?pool={}&amount=1000&direction=exact_zil_for_tokens", other),
+      format!("/quote?pool={}&out_pool={}&amount=1000&direction=exact_tokens_for_tokens", allowed, other),
+    ];
+    for uri in uris {
+      let res = test::call_service(&mut app, test::TestRequest::get().uri(&uri).to_request()).await;
+      assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+      let body: serde_json::Value = test::read_body_json(res).await;
+      assert_eq!(body["error"]["message"], format!("Token {} is not on the quote allowlist", other));
+    }
+  }
 }
@@ -0,0 +1,83 @@
+use actix_web::{get, HttpResponse, Responder};
+use prometheus::{Encoder, Histogram, HistogramVec, IntCounter, IntCounterVec, IntGauge, TextEncoder};
+use std::time::{Duration};
+
+lazy_static! {
+  static ref HTTP_REQUESTS_TOTAL: IntCounterVec = register_int_counter_vec!(
+    "zap_api_http_requests_total",
+    "Number of HTTP requests handled, by route and status.",
+    &["method", "route", "status"]
+  ).expect("failed to register metric");
+
+  static ref HTTP_REQUEST_DURATION_SECONDS: HistogramVec = register_histogram_vec!(
+    "zap_api_http_request_duration_seconds",
+    "HTTP request latency in seconds, by route.",
+    &["method", "route"]
+  ).expect("failed to register metric");
+
+  pub static ref DB_POOL_CHECKOUT_SECONDS: Histogram = register_histogram!(
+    "zap_api_db_pool_checkout_seconds",
+    "Time taken to check out a connection from the db pool in seconds.",
+    vec![0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 30.0]
+  ).expect("failed to register metric");
+
+  pub static ref WORKER_SYNC_HEIGHT: IntGauge = register_int_gauge!(
+    "zap_api_worker_sync_height",
+    "Highest block height fully processed by the worker."
+  ).expect("failed to register metric");
+
+  pub static ref WORKER_CHAIN_HEIGHT: IntGauge = register_int_gauge!(
+    "zap_api_worker_chain_height",
+    "Latest block height reported by the rpc node."
+  ).expect("failed to register metric");
+
+  pub static ref WORKER_SYNC_LAG: IntGauge = register_int_gauge!(
+    "zap_api_worker_sync_lag",
+    "Number of blocks the worker is behind the chain."
+  ).expect("failed to register metric");
+
+  pub static ref WORKER_JOBS_IN_FLIGHT: IntGauge = register_int_gauge!(
+    "zap_api_worker_jobs_in_flight",
+    "Number of fetch jobs queued to or running on the worker's fetchers."
+  ).expect("failed to register metric");
+
+  pub static ref WORKER_FETCH_ERRORS_TOTAL: IntCounter = register_int_counter!(
+    "zap_api_worker_fetch_errors_total",
+    "Number of fetch jobs that failed and were scheduled for retry."
+  ).expect("failed to register metric");
+
+  pub static ref RPC_ERRORS_TOTAL: IntCounterVec = register_int_counter_vec!(
+    "zap_api_rpc_errors_total",
+    "Number of failed rpc calls, by method.",
+    &["method"]
+  ).expect("failed to register metric");
+}
+
+/// Records a handled HTTP request. Requests that did not match any route
+/// are grouped together to keep the label cardinality bounded.
+pub fn observe_request(method: &str, route: Option<String>, status: u16, elapsed: Duration) {
+  let route = route.unwrap_or(String::from("unmatched"));
+  HTTP_REQUESTS_TOTAL.with_label_values(&[method, &route, &status.to_string()]).inc();
+  HTTP_REQUEST_DURATION_SECONDS.with_label_values(&[method, &route]).observe(elapsed.as_secs_f64());
+}
+
+/// Records the worker's sync position against the chain tip.
+pub fn set_sync_heights(sync_height: u32, chain_height: u32) {
+  WORKER_SYNC_HEIGHT.set(sync_height as i64);
+  WORKER_CHAIN_HEIGHT.set(chain_height as i64);
+  WORKER_SYNC_LAG.set(chain_height as i64 - sync_height as i64);
+}
+
+/// Prometheus metrics in the text exposition format.
+#[get("/metrics")]
+async fn get_metrics() -> impl Responder {
+  let mut buffer = vec![];
+  let encoder = TextEncoder::new();
+  match encoder.encode(&prometheus::gather(), &mut buffer) {
+    Ok(_) => HttpResponse::Ok().content_type(encoder.format_type()).body(buffer),
+    Err(e) => {
+      error!("failed to encode metrics: {}", e);
+      HttpResponse::InternalServerError().finish()
+    }
+  }
+}
@@ -1,10 +1,14 @@
-use bigdecimal::{BigDecimal};
+use bigdecimal::{BigDecimal, Signed};
 use chrono::{NaiveDateTime};
-use diesel::sql_types::{Text, Numeric};
+use diesel::sql_types::{BigInt, Integer, Text, Numeric, Timestamp};
 use serde::{Serialize, Deserialize};
+use serde_json::Value;
 use uuid::Uuid;
 
-use crate::schema::{swaps, liquidity_changes, distributions, claims, pool_txs, block_syncs};
+use crate::pagination::{Cursor, Keyset};
+use crate::tokens::{TokenMeta};
+use crate::utils::round_down;
+use crate::schema::{swaps, liquidity_changes, reserves, distributions, claims, pool_txs, block_syncs, failed_events};
 
 #[derive(Debug, Identifiable, Queryable, Serialize)]
 pub struct Swap {
@@ -18,6 +22,14 @@ pub struct Swap {
   pub token_amount: BigDecimal,
   pub zil_amount: BigDecimal,
   pub is_sending_zil: bool,
+  pub fee: Option<BigDecimal>,
+  pub success: bool,
+}
+
+impl Keyset for Swap {
+  fn cursor(&self) -> Cursor {
+    Cursor::new(self.block_timestamp, self.id)
+  }
 }
 
 #[derive(Debug, Insertable)]
@@ -32,6 +44,8 @@ pub struct NewSwap<'a> {
   pub token_amount: &'a BigDecimal,
   pub zil_amount: &'a BigDecimal,
   pub is_sending_zil: &'a bool,
+  pub fee: Option<&'a BigDecimal>,
+  pub success: &'a bool,
 }
 
 #[derive(Debug, Identifiable, Queryable, Serialize)]
@@ -46,6 +60,7 @@ pub struct LiquidityChange {
   pub change_amount: BigDecimal,
   pub token_amount: BigDecimal,
   pub zil_amount: BigDecimal,
+  pub success: bool,
 }
 
 #[derive(Debug, Insertable)]
@@ -60,6 +75,32 @@ pub struct NewLiquidityChange<'a> {
   pub change_amount: &'a BigDecimal,
   pub token_amount: &'a BigDecimal,
   pub zil_amount: &'a BigDecimal,
+  pub success: &'a bool,
+}
+
+/// The reserves of a pool after a `PoolSynced` event.
+#[derive(Debug, Identifiable, Queryable, Serialize)]
+pub struct Reserve {
+  pub id: Uuid,
+  pub transaction_hash: String,
+  pub event_sequence: i32,
+  pub block_height: i32,
+  pub block_timestamp: NaiveDateTime,
+  pub token_address: String,
+  pub zil_reserve: BigDecimal,
+  pub token_reserve: BigDecimal,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name="reserves"]
+pub struct NewReserve<'a> {
+  pub transaction_hash: &'a str,
+  pub event_sequence: &'a i32,
+  pub block_height: &'a i32,
+  pub block_timestamp: &'a NaiveDateTime,
+  pub token_address: &'a str,
+  pub zil_reserve: &'a BigDecimal,
+  pub token_reserve: &'a BigDecimal,
 }
 
 #[derive(Debug, Queryable, QueryableByName, Serialize, Deserialize, PartialEq)]
@@ -70,6 +111,69 @@ pub struct Liquidity {
   pub amount: BigDecimal,
 }
 
+#[derive(Debug, Queryable, Serialize)]
+pub struct LiquidityFlow {
+  pub pool: String,
+  pub added_amount: BigDecimal,
+  pub removed_amount: BigDecimal,
+}
+
+#[derive(Debug, QueryableByName, Serialize)]
+pub struct LpCount {
+  #[sql_type="Text"]
+  pub pool: String,
+  #[sql_type="BigInt"]
+  pub count: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SyncStatus {
+  pub indexed_height: i32,
+  pub chain_height: u32,
+  pub lag: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProofVerification {
+  pub valid: bool,
+  pub root: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PoolShare {
+  pub pool: String,
+  pub address: String,
+  pub amount: BigDecimal,
+  pub total_amount: BigDecimal,
+  pub share: BigDecimal,
+}
+
+impl PoolShare {
+  /// The share is the fraction of the pool's total liquidity held, or zero if the pool is empty.
+  pub fn new(pool: &str, address: &str, amount: BigDecimal, total_amount: BigDecimal) -> Self {
+    let share = if total_amount.is_positive() {
+      amount.clone() / total_amount.clone()
+    } else {
+      BigDecimal::default()
+    };
+    Self { pool: pool.to_owned(), address: address.to_owned(), amount, total_amount, share }
+  }
+}
+
+#[derive(Debug, QueryableByName, Serialize)]
+pub struct PoolLifespan {
+  #[sql_type="Text"]
+  pub pool: String,
+  #[sql_type="Integer"]
+  pub first_block_height: i32,
+  #[sql_type="Timestamp"]
+  pub first_block_timestamp: NaiveDateTime,
+  #[sql_type="Integer"]
+  pub last_block_height: i32,
+  #[sql_type="Timestamp"]
+  pub last_block_timestamp: NaiveDateTime,
+}
+
 #[derive(Debug, Queryable, QueryableByName, Serialize, PartialEq)]
 pub struct LiquidityFromProvider {
   #[sql_type="Text"]
@@ -102,6 +206,104 @@ pub struct Volume {
   pub in_token_amount: BigDecimal,
 }
 
+impl Volume {
+  /// Total volume of the pool in zil, counting the zil side of swaps in both directions.
+  pub fn zil_amount(&self) -> BigDecimal {
+    self.in_zil_amount.clone() + self.out_zil_amount.clone()
+  }
+}
+
+/// A pool's volume with the zil side of swaps in each direction valued in usd.
+#[derive(Debug, Serialize)]
+pub struct UsdVolume {
+  #[serde(flatten)]
+  pub volume: Volume,
+  pub in_usd_amount: BigDecimal,
+  pub out_usd_amount: BigDecimal,
+}
+
+impl UsdVolume {
+  /// Values the volume at the given usd price of a zil. Zil amounts are in qa (10^-12 zil).
+  pub fn new(volume: Volume, zil_usd_price: &BigDecimal) -> Self {
+    let qa_usd_price = zil_usd_price / BigDecimal::from(1_000_000_000_000i64);
+    let in_usd_amount = round_down(&volume.in_zil_amount * &qa_usd_price, 6);
+    let out_usd_amount = round_down(&volume.out_zil_amount * &qa_usd_price, 6);
+    Self { volume, in_usd_amount, out_usd_amount }
+  }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DenominatedVolume {
+  pub pool: String,
+  pub denom: String,
+  pub amount: BigDecimal,
+}
+
+/// A pool's current reserves, liquidity and 24h volume in zil. Reserves
+/// are left out if they could not be fetched from the chain.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PoolInfo {
+  pub pool: String,
+  pub zil_reserve: Option<BigDecimal>,
+  pub token_reserve: Option<BigDecimal>,
+  pub total_liquidity: BigDecimal,
+  pub volume_24h: BigDecimal,
+  pub has_liquidity: bool,
+}
+
+/// A pool ticker in the format expected by CoinGecko. Prices and volumes
+/// are in whole tokens and zil rather than base units.
+#[derive(Debug, Serialize)]
+pub struct Ticker {
+  pub ticker_id: String,
+  pub base_currency: String,
+  pub target_currency: String,
+  pub last_price: BigDecimal,
+  pub base_volume: BigDecimal,
+  pub target_volume: BigDecimal,
+  pub liquidity_in_usd: Option<BigDecimal>,
+}
+
+impl Ticker {
+  /// Builds the ticker of a pool from its last swap and 24h volume, scaling amounts by the
+  /// decimals of its token. The liquidity in usd counts both sides of the pool at the value
+  /// of its zil reserve, and is left out if the reserve or the zil price is unknown.
+  pub fn new(
+    last_swap: Swap,
+    volume: Option<&Volume>,
+    token: &TokenMeta,
+    zil_reserve: Option<&BigDecimal>,
+    zil_usd_price: Option<&BigDecimal>,
+  ) -> Self {
+    let qa_per_zil = BigDecimal::from(1_000_000_000_000i64);
+    let units_per_token = BigDecimal::new(1.into(), -i64::from(token.decimals));
+
+    let last_price = if last_swap.token_amount.is_positive() {
+      (last_swap.zil_amount / &qa_per_zil) / (last_swap.token_amount / &units_per_token)
+    } else {
+      BigDecimal::default()
+    };
+    let (base_volume, target_volume) = match volume {
+      Some(v) => ((&v.in_token_amount + &v.out_token_amount) / &units_per_token, v.zil_amount() / &qa_per_zil),
+      None => (BigDecimal::default(), BigDecimal::default()),
+    };
+    let liquidity_in_usd = match (zil_reserve, zil_usd_price) {
+      (Some(reserve), Some(price)) => Some(round_down(reserve * BigDecimal::from(2) / &qa_per_zil * price, 6)),
+      _ => None,
+    };
+
+    Self {
+      ticker_id: format!("{}_ZIL", token.symbol),
+      base_currency: last_swap.token_address,
+      target_currency: String::from("ZIL"),
+      last_price,
+      base_volume,
+      target_volume,
+      liquidity_in_usd,
+    }
+  }
+}
+
 #[derive(Debug, Identifiable, Queryable, Serialize)]
 pub struct PoolTx {
   pub id: Uuid,
@@ -149,6 +351,19 @@ pub struct NewDistribution<'a> {
   pub proof: &'a str,
 }
 
+/// Amounts are serialized as strings, so they are not rounded by json parsers.
+#[derive(Debug, QueryableByName, Serialize)]
+pub struct DistributionSummary {
+  #[sql_type="Text"]
+  pub distributor_address: String,
+  #[sql_type="Numeric"]
+  pub distributed_amount: BigDecimal,
+  #[sql_type="Numeric"]
+  pub claimed_amount: BigDecimal,
+  #[sql_type="Numeric"]
+  pub claimable_amount: BigDecimal,
+}
+
 #[derive(Debug, Identifiable, Queryable, Serialize)]
 pub struct Claim {
   pub id: Uuid,
@@ -160,6 +375,8 @@ pub struct Claim {
   pub distributor_address: String,
   pub epoch_number: i32,
   pub amount: BigDecimal,
+  pub fee: Option<BigDecimal>,
+  pub success: bool,
 }
 
 #[derive(Debug, Clone, Insertable)]
@@ -173,6 +390,8 @@ pub struct NewClaim<'a> {
   pub distributor_address: &'a str,
   pub epoch_number: &'a i32,
   pub amount: &'a BigDecimal,
+  pub fee: Option<&'a BigDecimal>,
+  pub success: &'a bool,
 }
 
 #[derive(Debug, Clone, Identifiable, Queryable, Serialize)]
@@ -181,6 +400,13 @@ pub struct BlockSync {
   pub block_height: i32,
   pub block_timestamp: NaiveDateTime,
   pub num_txs: i32,
+  pub block_hash: Option<String>,
+}
+
+#[derive(Debug, QueryableByName)]
+pub struct BlockHeight {
+  #[sql_type="Integer"]
+  pub block_height: i32,
 }
 
 #[derive(Debug, Clone, Insertable)]
@@ -189,4 +415,77 @@ pub struct NewBlockSync<'a> {
   pub block_height: &'a i32,
   pub block_timestamp: &'a NaiveDateTime,
   pub num_txs: &'a i32,
+  pub block_hash: &'a str,
+}
+
+#[derive(Debug, Identifiable, Queryable, Serialize)]
+pub struct FailedEvent {
+  pub id: Uuid,
+  pub transaction_hash: String,
+  pub event_sequence: i32,
+  pub block_height: i32,
+  pub block_timestamp: NaiveDateTime,
+  pub contract_address: String,
+  pub initiator_address: String,
+  pub event_name: String,
+  pub params: Value,
+  pub error: String,
+  pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name="failed_events"]
+pub struct NewFailedEvent<'a> {
+  pub transaction_hash: &'a str,
+  pub event_sequence: &'a i32,
+  pub block_height: &'a i32,
+  pub block_timestamp: &'a NaiveDateTime,
+  pub contract_address: &'a str,
+  pub initiator_address: &'a str,
+  pub event_name: &'a str,
+  pub params: &'a Value,
+  pub error: &'a str,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::str::FromStr;
+
+  #[test]
+  fn test_ticker_scales_by_decimals() {
+    let swap = Swap {
+      id: Uuid::nil(),
+      transaction_hash: String::from("0x01"),
+      event_sequence: 0,
+      block_height: 1,
+      block_timestamp: NaiveDateTime::from_timestamp(1_600_000_000, 0),
+      initiator_address: String::from("zil1zjvc2m9f5vh8zl57su5j8lflgaq2lx08kcwdvy"),
+      token_address: String::from("zil1p5suryq6q647usxczale29cu3336hhp376c627"),
+      // 300 zil for 2 tokens of 12 decimals
+      token_amount: BigDecimal::from(2_000_000_000_000i64),
+      zil_amount: BigDecimal::from(300_000_000_000_000i64),
+      is_sending_zil: true,
+      fee: None,
+      success: true,
+    };
+    let volume = Volume {
+      pool: swap.token_address.clone(),
+      in_zil_amount: BigDecimal::from(300_000_000_000_000i64),
+      out_token_amount: BigDecimal::from(2_000_000_000_000i64),
+      out_zil_amount: BigDecimal::from(150_000_000_000_000i64),
+      in_token_amount: BigDecimal::from(1_000_000_000_000i64),
+    };
+    let token = TokenMeta { name: String::from("Zilswap"), symbol: String::from("ZWAP"), decimals: 12 };
+    let zil_reserve = BigDecimal::from(1_000_000_000_000_000i64);
+    let price = BigDecimal::from_str("0.05").unwrap();
+
+    let ticker = Ticker::new(swap, Some(&volume), &token, Some(&zil_reserve), Some(&price));
+    assert_eq!(ticker.ticker_id, "ZWAP_ZIL");
+    assert_eq!(ticker.base_currency, "zil1p5suryq6q647usxczale29cu3336hhp376c627");
+    assert_eq!(ticker.last_price, BigDecimal::from(150));
+    assert_eq!(ticker.base_volume, BigDecimal::from(3));
+    assert_eq!(ticker.target_volume, BigDecimal::from(450));
+    assert_eq!(ticker.liquidity_in_usd, Some(BigDecimal::from(100)));
+  }
 }
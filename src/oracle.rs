@@ -0,0 +1,40 @@
+use bigdecimal::BigDecimal;
+use serde::Deserialize;
+use std::time::Duration;
+
+use crate::utils::FetchError;
+
+#[derive(Deserialize)]
+struct SimplePrice {
+  zilliqa: UsdPrice,
+}
+
+#[derive(Deserialize)]
+struct UsdPrice {
+  usd: BigDecimal,
+}
+
+/// Client for a price api in the CoinGecko `simple/price` format,
+/// e.g. `{"zilliqa":{"usd":0.1}}`.
+#[derive(Clone)]
+pub struct PriceOracle {
+  url: String,
+  http_client: reqwest::Client,
+}
+
+impl PriceOracle {
+  pub fn new(url: &str, timeout: Duration) -> PriceOracle {
+    let http_client = reqwest::Client::builder()
+      .connect_timeout(timeout)
+      .timeout(timeout)
+      .build()
+      .expect("failed to build http client");
+    PriceOracle { url: url.to_owned(), http_client }
+  }
+
+  /// Fetches the price of a zil in usd.
+  pub async fn get_zil_usd_price(&self) -> Result<BigDecimal, FetchError> {
+    let price: SimplePrice = self.http_client.get(&self.url).send().await?.error_for_status()?.json().await?;
+    Ok(price.zilliqa.usd)
+  }
+}
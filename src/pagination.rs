@@ -2,9 +2,12 @@ use diesel::pg::Pg;
 use diesel::prelude::*;
 use diesel::query_builder::*;
 use diesel::query_dsl::methods::LoadQuery;
-use diesel::sql_types::BigInt;
+use diesel::sql_types::{BigInt, Timestamp, Uuid as UuidType};
+use chrono::{NaiveDateTime};
 use serde::{Serialize};
 use std::cmp::{max, min};
+use std::sync::atomic::{AtomicI64, Ordering};
+use uuid::Uuid;
 
 pub trait Paginate: Sized {
     fn paginate(self, page: Option<i64>) -> Paginated<Self>;
@@ -14,7 +17,7 @@ impl<T> Paginate for T {
     fn paginate(self, page: Option<i64>) -> Paginated<Self> {
       let r = Paginated {
         query: self,
-        per_page: DEFAULT_PER_PAGE,
+        per_page: default_per_page(),
         page: 1,
       };
       match page {
@@ -24,8 +27,18 @@ impl<T> Paginate for T {
     }
 }
 
-const DEFAULT_PER_PAGE: i64 = 10;
-const MAXIMUM_PER_PAGE: i64 = 50;
+static DEFAULT_PER_PAGE: AtomicI64 = AtomicI64::new(10);
+static MAXIMUM_PER_PAGE: AtomicI64 = AtomicI64::new(50);
+
+/// Sets the default and maximum number of records per page. Should be called once at startup.
+pub fn configure(default_per_page: i64, maximum_per_page: i64) {
+    MAXIMUM_PER_PAGE.store(max(maximum_per_page, 1), Ordering::Relaxed);
+    DEFAULT_PER_PAGE.store(clamp_per_page(default_per_page), Ordering::Relaxed);
+}
+
+fn default_per_page() -> i64 {
+    DEFAULT_PER_PAGE.load(Ordering::Relaxed)
+}
 
 #[derive(Debug, Clone, Copy, QueryId)]
 pub struct Paginated<T> {
@@ -37,13 +50,31 @@ pub struct Paginated<T> {
 #[derive(Serialize)]
 pub struct PaginatedResult<T> {
   records: Vec<T>,
-  total_pages: i64
+  total_pages: i64,
+  has_next: bool,
+  has_prev: bool,
+}
+
+impl<T> PaginatedResult<T> {
+  /// Maps each record of the page, keeping its page count.
+  pub fn map<U, F: FnMut(T) -> U>(self, f: F) -> PaginatedResult<U> {
+    PaginatedResult {
+      records: self.records.into_iter().map(f).collect(),
+      total_pages: self.total_pages,
+      has_next: self.has_next,
+      has_prev: self.has_prev,
+    }
+  }
+}
+
+fn clamp_per_page(per_page: i64) -> i64 {
+    max(min(MAXIMUM_PER_PAGE.load(Ordering::Relaxed), per_page), 1)
 }
 
 impl<T> Paginated<T> {
     pub fn per_page(self, per_page: Option<i64>) -> Self {
         match per_page {
-          Some(p) => Paginated { per_page: max(min(MAXIMUM_PER_PAGE, p), 1), ..self },
+          Some(p) => Paginated { per_page: clamp_per_page(p), ..self },
           None => self
         }
     }
@@ -53,11 +84,15 @@ impl<T> Paginated<T> {
         Self: LoadQuery<PgConnection, (U, i64)>,
     {
         let per_page = self.per_page;
+        let page = self.page;
         let results = self.load::<(U, i64)>(conn)?;
         let total = results.get(0).map(|x| x.1).unwrap_or(0);
         let records = results.into_iter().map(|x| x.0).collect();
         let total_pages = (total as f64 / per_page as f64).ceil() as i64;
-        Ok(PaginatedResult{ records: records, total_pages: total_pages })
+        // pages past the last one have no records to count, so they only have a previous page
+        let has_next = page < total_pages;
+        let has_prev = page > 1;
+        Ok(PaginatedResult{ records: records, total_pages: total_pages, has_next, has_prev })
     }
 }
 
@@ -82,3 +117,127 @@ where
         Ok(())
     }
 }
+
+/// Position of a record in a result set ordered by `(block_timestamp, id)` descending.
+#[derive(Debug, Clone, Copy)]
+pub struct Cursor {
+    block_timestamp: NaiveDateTime,
+    id: Uuid,
+}
+
+impl Cursor {
+    pub fn new(block_timestamp: NaiveDateTime, id: Uuid) -> Cursor {
+        Cursor { block_timestamp, id }
+    }
+
+    /// Decodes an opaque cursor previously returned as `next_cursor`.
+    pub fn decode(input: &str) -> Option<Cursor> {
+        let bytes = base64::decode_config(input, base64::URL_SAFE_NO_PAD).ok()?;
+        let decoded = String::from_utf8(bytes).ok()?;
+        let mut parts = decoded.splitn(3, ':');
+        let secs = parts.next()?.parse::<i64>().ok()?;
+        let nanos = parts.next()?.parse::<u32>().ok()?;
+        let id = Uuid::parse_str(parts.next()?).ok()?;
+        let block_timestamp = NaiveDateTime::from_timestamp_opt(secs, nanos)?;
+        Some(Cursor { block_timestamp, id })
+    }
+
+    pub fn encode(&self) -> String {
+        let raw = format!("{}:{}:{}", self.block_timestamp.timestamp(), self.block_timestamp.timestamp_subsec_nanos(), self.id);
+        base64::encode_config(raw, base64::URL_SAFE_NO_PAD)
+    }
+}
+
+/// Records that can be paginated by keyset.
+pub trait Keyset {
+    fn cursor(&self) -> Cursor;
+}
+
+pub trait PaginateAfter: Sized {
+    fn paginate_after(self, cursor: Option<Cursor>) -> PaginatedAfter<Self>;
+}
+
+impl<T> PaginateAfter for T {
+    fn paginate_after(self, cursor: Option<Cursor>) -> PaginatedAfter<Self> {
+      PaginatedAfter {
+        query: self,
+        cursor,
+        per_page: default_per_page(),
+      }
+    }
+}
+
+/// Keyset pagination over queries with `block_timestamp` and `id` columns.
+/// Unlike `Paginated`, the cost of fetching a page does not grow with its depth.
+#[derive(Debug, Clone, Copy, QueryId)]
+pub struct PaginatedAfter<T> {
+    query: T,
+    cursor: Option<Cursor>,
+    per_page: i64,
+}
+
+#[derive(Serialize)]
+pub struct CursorPaginatedResult<T> {
+  records: Vec<T>,
+  next_cursor: Option<String>,
+}
+
+impl<T> CursorPaginatedResult<T> {
+  /// Maps each record of the page, keeping its cursor.
+  pub fn map<U, F: FnMut(T) -> U>(self, f: F) -> CursorPaginatedResult<U> {
+    CursorPaginatedResult { records: self.records.into_iter().map(f).collect(), next_cursor: self.next_cursor }
+  }
+}
+
+impl<T> PaginatedAfter<T> {
+    pub fn per_page(self, per_page: Option<i64>) -> Self {
+        match per_page {
+          Some(p) => PaginatedAfter { per_page: clamp_per_page(p), ..self },
+          None => self
+        }
+    }
+
+    pub fn load_with_cursor<U>(self, conn: &PgConnection) -> QueryResult<CursorPaginatedResult<U>>
+    where
+        Self: LoadQuery<PgConnection, U>,
+        U: Keyset,
+    {
+        let per_page = self.per_page as usize;
+        // fetch one extra record to know whether there is a next page
+        let mut records = PaginatedAfter { per_page: self.per_page + 1, ..self }.load::<U>(conn)?;
+        let next_cursor = if records.len() > per_page {
+          records.truncate(per_page);
+          records.last().map(|r| r.cursor().encode())
+        } else {
+          None
+        };
+        Ok(CursorPaginatedResult{ records, next_cursor })
+    }
+}
+
+impl<T: Query> Query for PaginatedAfter<T> {
+    type SqlType = T::SqlType;
+}
+
+impl<T> RunQueryDsl<PgConnection> for PaginatedAfter<T> {}
+
+impl<T> QueryFragment<Pg> for PaginatedAfter<T>
+where
+    T: QueryFragment<Pg>,
+{
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        out.push_sql("SELECT * FROM (");
+        self.query.walk_ast(out.reborrow())?;
+        out.push_sql(") t");
+        if let Some(cursor) = &self.cursor {
+          out.push_sql(" WHERE (t.block_timestamp, t.id) < (");
+          out.push_bind_param::<Timestamp, _>(&cursor.block_timestamp)?;
+          out.push_sql(", ");
+          out.push_bind_param::<UuidType, _>(&cursor.id)?;
+          out.push_sql(")");
+        }
+        out.push_sql(" ORDER BY t.block_timestamp DESC, t.id DESC LIMIT ");
+        out.push_bind_param::<BigInt, _>(&self.per_page)?;
+        Ok(())
+    }
+}
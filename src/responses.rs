@@ -35,3 +35,12 @@ pub struct ViewBlockEvent {
   pub details: String,
   pub params: Value,
 }
+
+/// A token from the ZilStream token list.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZilStreamToken {
+  pub name: String,
+  pub symbol: String,
+  pub address_bech32: String,
+  pub decimals: u32,
+}
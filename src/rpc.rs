@@ -1,17 +1,24 @@
+use bigdecimal::BigDecimal;
 use reqwest::blocking::Client;
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Value};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use strum_macros::Display;
 
+use crate::metrics;
 use crate::utils;
 
 #[derive(Display, Clone)]
 pub enum RPCMethod {
   GetTransaction,
-  GetTransactionsForTxBlock,
+  GetTransactionsForTxBlockEx,
   GetNumTxBlocks,
   GetTxBlock,
+  GetSmartContractSubState,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -19,7 +26,7 @@ pub struct RPCRequest {
   id: i32,
   jsonrpc: String,
   method: String,
-  params: Vec<String>,
+  params: Vec<Value>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -58,6 +65,7 @@ pub struct TxReceipt {
   pub accepted: Option<bool>,
   pub event_logs: Option<Vec<MaybeTxEvent>>,
   pub transitions: Option<Vec<TxTransition>>,
+  pub cumulative_gas: Option<String>,
 }
 
 impl TxReceipt {
@@ -100,6 +108,16 @@ pub struct TxResult {
   pub gas_price: String,
 }
 
+impl TxResult {
+  /// The fee paid for the transaction in Qa (gas used × gas price),
+  /// if the node reports the gas used.
+  pub fn fee(&self) -> Option<BigDecimal> {
+    let gas_used = self.receipt.cumulative_gas.as_ref()?.parse::<BigDecimal>().ok()?;
+    let gas_price = self.gas_price.parse::<BigDecimal>().ok()?;
+    Some(gas_used * gas_price)
+  }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "PascalCase")]
 pub struct BlockHeader {
@@ -134,36 +152,150 @@ impl BlockTxsResult {
   }
 }
 
+/// A page of a block's transactions. Large blocks are split into several pages.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct BlockTxsPage {
+  pub curr_page: u32,
+  pub num_pages: u32,
+  pub transactions: BlockTxsResult,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RPCError {
+  pub code: i64,
+  pub message: String,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct RPCResponse {
   pub id: i32,
   pub jsonrpc: String,
+  #[serde(default)]
   pub result: Value,
+  pub error: Option<RPCError>,
+}
+
+impl RPCResponse {
+  fn into_result(self) -> Result<Value, utils::FetchError> {
+    match self.error {
+      Some(error) => Err(utils::FetchError::Rpc(error.code, error.message)),
+      None => Ok(self.result),
+    }
+  }
+}
+
+/// The rpc endpoints of a client and the one that last responded, which calls
+/// start from. Clones share the same current endpoint.
+#[derive(Clone)]
+struct RpcEndpoints {
+  rpc_urls: Vec<String>,
+  current: Arc<AtomicUsize>,
 }
 
+impl RpcEndpoints {
+  fn new(rpc_urls: &[String]) -> RpcEndpoints {
+    assert!(!rpc_urls.is_empty(), "at least one rpc url is required");
+    Self {
+      rpc_urls: rpc_urls.to_vec(),
+      current: Arc::new(AtomicUsize::new(0)),
+    }
+  }
+
+  /// Each endpoint with its index, starting from the current one.
+  fn in_order(&self) -> impl Iterator<Item = (usize, &str)> + '_ {
+    let start = self.current.load(Ordering::Relaxed);
+    (0..self.rpc_urls.len()).map(move |i| {
+      let index = (start + i) % self.rpc_urls.len();
+      (index, self.rpc_urls[index].as_str())
+    })
+  }
+
+  /// Makes the endpoint at `index` the current one after it responded.
+  fn responded(&self, index: usize) {
+    if self.current.swap(index, Ordering::Relaxed) != index {
+      warn!("rpc failed over to {}", self.rpc_urls[index]);
+    }
+  }
+}
+
+/// Client for the Zilliqa json-rpc api. Calls go to the last endpoint that
+/// responded, failing over to the next one on transport errors. Clones share
+/// the same endpoint state.
 #[derive(Clone)]
 pub struct ZilliqaClient {
-  rpc_url: String,
+  endpoints: RpcEndpoints,
   http_client: Client,
 }
 
 impl ZilliqaClient {
-  pub fn new(rpc_url: &str) -> ZilliqaClient {
+  pub fn new(rpc_urls: &[String], timeout: Duration) -> ZilliqaClient {
+    let http_client = Client::builder()
+      .connect_timeout(timeout)
+      .timeout(timeout)
+      .build()
+      .expect("failed to build http client");
     Self {
-      rpc_url: rpc_url.to_string(),
-      http_client: Client::new(),
+      endpoints: RpcEndpoints::new(rpc_urls),
+      http_client,
     }
   }
   pub fn rpc_call(&self, rpc_method: RPCMethod, params: Vec<String>) -> Result<Value, utils::FetchError>  {
     let method = rpc_method.to_string();
-    trace!("call {} {}", method, self.rpc_url);
-    let url = Url::parse(self.rpc_url.as_str()).expect("URL parsing failed!");
+    let result = self.with_failover(&method, |rpc_url| self.send(rpc_url, &method, params.clone()));
+    if result.is_err() {
+      metrics::RPC_ERRORS_TOTAL.with_label_values(&[&method]).inc();
+    }
+    result
+  }
+
+  /// Calls the same method once for each of the given params in a single
+  /// json-rpc batch request. Results are returned in the order of the params.
+  pub fn rpc_batch_call(&self, rpc_method: RPCMethod, params_list: Vec<Vec<String>>) -> Result<Vec<Value>, utils::FetchError>  {
+    if params_list.is_empty() {
+      return Ok(vec![])
+    }
+    let method = rpc_method.to_string();
+    let result = self.with_failover(&method, |rpc_url| self.send_batch(rpc_url, &method, params_list.clone()));
+    if result.is_err() {
+      metrics::RPC_ERRORS_TOTAL.with_label_values(&[&method]).inc();
+    }
+    result
+  }
+
+  /// Tries each endpoint once, starting from the current one, until one
+  /// responds. Only transport errors cause a failover.
+  fn with_failover<T, F>(&self, method: &str, send: F) -> Result<T, utils::FetchError>
+  where F: Fn(&str) -> Result<T, utils::FetchError> {
+    let mut error = None;
+    for (index, rpc_url) in self.endpoints.in_order() {
+      match send(rpc_url) {
+        Err(utils::FetchError::Fetch(e)) => {
+          warn!("rpc call {} to {} failed: {}", method, rpc_url, e);
+          error = Some(e);
+        },
+        result => {
+          self.endpoints.responded(index);
+          if result.is_ok() {
+            trace!("rpc call {} served by {}", method, rpc_url);
+          }
+          return result
+        },
+      }
+    }
+    Err(utils::FetchError::Fetch(error.expect("at least one rpc url is required")))
+  }
+
+  fn send(&self, rpc_url: &str, method: &str, params: Vec<String>) -> Result<Value, utils::FetchError> {
+    let method = method.to_string();
+    trace!("call {} {}", method, rpc_url);
+    let url = Url::parse(rpc_url).expect("URL parsing failed!");
 
     let request = RPCRequest { 
       id: 1, 
       jsonrpc: "2.0".to_string(),
       method,
-      params,
+      params: params.into_iter().map(Value::from).collect(),
     };
     let payload = serde_json::to_string(&request).unwrap();
     trace!("payload {}", payload);
@@ -173,32 +305,291 @@ impl ZilliqaClient {
     trace!("response {}", body);
 
     let rpc_response: RPCResponse = serde_json::from_str(body.as_str())?;
-    return Ok(rpc_response.result);
+    rpc_response.into_result()
+  }
+
+  fn send_batch(&self, rpc_url: &str, method: &str, params_list: Vec<Vec<String>>) -> Result<Vec<Value>, utils::FetchError> {
+    trace!("batch call {} x{} {}", method, params_list.len(), rpc_url);
+    let url = Url::parse(rpc_url).expect("URL parsing failed!");
+
+    let requests: Vec<RPCRequest> = params_list.into_iter().enumerate().map(|(i, params)| {
+      RPCRequest {
+        id: i as i32,
+        jsonrpc: "2.0".to_string(),
+        method: method.to_string(),
+        params: params.into_iter().map(Value::from).collect(),
+      }
+    }).collect();
+    let count = requests.len();
+    let payload = serde_json::to_string(&requests).unwrap();
+    trace!("payload {}", payload);
+
+    let resp = self.http_client.post(url).body(payload).send()?;
+    let body = resp.text()?;
+    trace!("response {}", body);
+
+    // responses to a batch may come in any order
+    let mut rpc_responses: Vec<RPCResponse> = serde_json::from_str(body.as_str())?;
+    rpc_responses.sort_by_key(|r| r.id);
+    if rpc_responses.len() != count || rpc_responses.iter().enumerate().any(|(i, r)| r.id != i as i32) {
+      return Err(utils::FetchError::MalformedBatch(format!("batch response does not match the {} requests sent", count)));
+    }
+    rpc_responses.into_iter().map(|r| r.into_result()).collect()
   }
 
   pub fn get_transaction(&self, tx_hash: &String) -> Result<TxResult, utils::FetchError> {
     let result = self.rpc_call(RPCMethod::GetTransaction, vec![tx_hash.clone()])?;
-    let tx_result = serde_json::from_value(result).unwrap();
+    let tx_result = serde_json::from_value(result)?;
     return Ok(tx_result);
   }
 
   pub fn get_block(&self, block_height: &u32) -> Result<BlockResult, utils::FetchError> {
     let result = self.rpc_call(RPCMethod::GetTxBlock, vec![block_height.to_string()])?;
-    let blk_result = serde_json::from_value(result).unwrap();
+    let blk_result = serde_json::from_value(result)?;
     return Ok(blk_result);
   }
 
   pub fn get_latest_block(&self) -> Result<u32, utils::FetchError> {
     let result = self.rpc_call(RPCMethod::GetNumTxBlocks, vec![])?;
-    let blk_result_string: String = serde_json::from_value(result).unwrap();
-    let blk_result = blk_result_string.parse::<u32>().unwrap();
+    let blk_result_string: String = serde_json::from_value(result)?;
+    let blk_result = blk_result_string.parse::<u32>()
+      .map_err(|_| utils::FetchError::Rpc(0, format!("invalid block height: {}", blk_result_string)))?;
 
     return Ok(blk_result);
   }
 
-  pub fn get_block_txs(&self, block_height: &u32) -> Result<BlockTxsResult, utils::FetchError> {
-    let result = self.rpc_call(RPCMethod::GetTransactionsForTxBlock, vec![block_height.to_string()])?;
-    let txs_result = serde_json::from_value(result).unwrap();
-    return Ok(txs_result);
+  pub fn get_block_txs_paged(&self, block_height: &u32, page: u32) -> Result<BlockTxsPage, utils::FetchError> {
+    let result = self.rpc_call(RPCMethod::GetTransactionsForTxBlockEx, vec![block_height.to_string(), page.to_string()])?;
+    let txs_page = serde_json::from_value(result)?;
+    return Ok(txs_page);
+  }
+
+  pub fn get_transactions(&self, tx_hashes: &[String]) -> Result<Vec<TxResult>, utils::FetchError> {
+    let params = tx_hashes.iter().map(|h| vec![h.clone()]).collect();
+    let results = self.rpc_batch_call(RPCMethod::GetTransaction, params)?;
+    Ok(results.into_iter().map(serde_json::from_value).collect::<Result<Vec<TxResult>, _>>()?)
+  }
+
+  pub fn get_blocks(&self, block_heights: &[u32]) -> Result<Vec<BlockResult>, utils::FetchError> {
+    let params = block_heights.iter().map(|h| vec![h.to_string()]).collect();
+    let results = self.rpc_batch_call(RPCMethod::GetTxBlock, params)?;
+    Ok(results.into_iter().map(serde_json::from_value).collect::<Result<Vec<BlockResult>, _>>()?)
+  }
+
+  /// Gets the first page of transactions of each of the given blocks.
+  pub fn get_blocks_txs(&self, block_heights: &[u32]) -> Result<Vec<BlockTxsPage>, utils::FetchError> {
+    let params = block_heights.iter().map(|h| vec![h.to_string(), String::from("0")]).collect();
+    let results = self.rpc_batch_call(RPCMethod::GetTransactionsForTxBlockEx, params)?;
+    Ok(results.into_iter().map(serde_json::from_value).collect::<Result<Vec<BlockTxsPage>, _>>()?)
+  }
+}
+
+/// Async client for the Zilliqa json-rpc api, for use from request handlers
+/// on the actix runtime. Fails over between endpoints like `ZilliqaClient`.
+#[derive(Clone)]
+pub struct AsyncZilliqaClient {
+  endpoints: RpcEndpoints,
+  http_client: reqwest::Client,
+  latest_block: Arc<Mutex<Option<(u32, Instant)>>>,
+}
+
+impl AsyncZilliqaClient {
+  pub fn new(rpc_urls: &[String], timeout: Duration) -> AsyncZilliqaClient {
+    let http_client = reqwest::Client::builder()
+      .connect_timeout(timeout)
+      .timeout(timeout)
+      .build()
+      .expect("failed to build http client");
+    Self {
+      endpoints: RpcEndpoints::new(rpc_urls),
+      http_client,
+      latest_block: Arc::new(Mutex::new(None)),
+    }
+  }
+
+  pub async fn rpc_call(&self, rpc_method: RPCMethod, params: Vec<Value>) -> Result<Value, utils::FetchError> {
+    let method = rpc_method.to_string();
+    let result = self.with_failover(&method, params).await;
+    if result.is_err() {
+      metrics::RPC_ERRORS_TOTAL.with_label_values(&[&method]).inc();
+    }
+    result
+  }
+
+  /// Tries each endpoint once like `ZilliqaClient::with_failover`.
+  async fn with_failover(&self, method: &str, params: Vec<Value>) -> Result<Value, utils::FetchError> {
+    let mut error = None;
+    for (index, rpc_url) in self.endpoints.in_order() {
+      match self.send(rpc_url, method, params.clone()).await {
+        Err(utils::FetchError::Fetch(e)) => {
+          warn!("rpc call {} to {} failed: {}", method, rpc_url, e);
+          error = Some(e);
+        },
+        result => {
+          self.endpoints.responded(index);
+          if result.is_ok() {
+            trace!("rpc call {} served by {}", method, rpc_url);
+          }
+          return result
+        },
+      }
+    }
+    Err(utils::FetchError::Fetch(error.expect("at least one rpc url is required")))
+  }
+
+  async fn send(&self, rpc_url: &str, method: &str, params: Vec<Value>) -> Result<Value, utils::FetchError> {
+    trace!("call {} {}", method, rpc_url);
+    let url = Url::parse(rpc_url).expect("URL parsing failed!");
+
+    let request = RPCRequest {
+      id: 1,
+      jsonrpc: "2.0".to_string(),
+      method: method.to_string(),
+      params,
+    };
+
+    let resp = self.http_client.post(url).json(&request).send().await?;
+    let body = resp.text().await?;
+    trace!("response {}", body);
+
+    let rpc_response: RPCResponse = serde_json::from_str(body.as_str())?;
+    rpc_response.into_result()
+  }
+
+  pub async fn get_latest_block(&self) -> Result<u32, utils::FetchError> {
+    let result = self.rpc_call(RPCMethod::GetNumTxBlocks, vec![]).await?;
+    let blk_result_string: String = serde_json::from_value(result)?;
+    blk_result_string.parse::<u32>()
+      .map_err(|_| utils::FetchError::Rpc(0, format!("invalid block height: {}", blk_result_string)))
+  }
+
+  /// Gets the latest block height, reusing the last result if it is younger than `max_age`.
+  pub async fn get_latest_block_cached(&self, max_age: Duration) -> Result<u32, utils::FetchError> {
+    if let Some((height, fetched_at)) = *self.latest_block.lock().unwrap() {
+      if fetched_at.elapsed() < max_age {
+        return Ok(height)
+      }
+    }
+    let height = self.get_latest_block().await?;
+    *self.latest_block.lock().unwrap() = Some((height, Instant::now()));
+    Ok(height)
+  }
+
+  /// Gets a field of a contract's state, as a json object with the field as its only key.
+  pub async fn get_smart_contract_sub_state(&self, contract_address: &str, field: &str) -> Result<Value, utils::FetchError> {
+    let address = contract_address.trim_start_matches("0x");
+    self.rpc_call(RPCMethod::GetSmartContractSubState, vec![json!(address), json!(field), json!([])]).await
+  }
+
+  /// Gets the reserves of each pool in the zilswap contract, with the contract's fee rate
+  /// if it can be read. Pools are left without a fee rate otherwise.
+  pub async fn get_pool_reserves(&self, contract_address: &str) -> Result<Vec<PoolReserves>, utils::FetchError> {
+    let result = self.get_smart_contract_sub_state(contract_address, "pools").await?;
+    let fee_rate = match self.get_smart_contract_sub_state(contract_address, "output_after_fee").await {
+      Ok(state) => PoolReserves::fee_rate_from_state(&state),
+      Err(e) => {
+        warn!("Failed to fetch fee rate: {}", e);
+        None
+      }
+    };
+    Ok(PoolReserves::from_state(&result)?.into_iter().map(|r| PoolReserves { fee_rate: fee_rate.clone(), ..r }).collect())
+  }
+}
+
+/// The reserves of a zilswap pool.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolReserves {
+  pub token_address: String,
+  pub zil_reserve: BigDecimal,
+  pub token_reserve: BigDecimal,
+  /// The fraction of inputs taken as a fee, if known.
+  #[serde(default)]
+  pub fee_rate: Option<BigDecimal>,
+}
+
+impl PoolReserves {
+  /// Parses the `pools` field of the zilswap contract's state, a map of token
+  /// addresses in hex to `Pool(zil reserve, token reserve)`. Token addresses
+  /// are converted to bech32, like the pools of indexed events.
+  pub fn from_state(state: &Value) -> Result<Vec<PoolReserves>, utils::FetchError> {
+    let pools = match state.get("pools").and_then(|p| p.as_object()) {
+      Some(pools) => pools,
+      None => return Ok(vec![]),
+    };
+    pools.iter().map(|(token_address, pool)| {
+      let invalid = || utils::FetchError::Rpc(0, format!("invalid reserves for pool {}", token_address));
+      let reserve = |i: usize| pool["arguments"].get(i)
+        .and_then(|r| r.as_str())
+        .and_then(|r| BigDecimal::from_str(r).ok())
+        .ok_or_else(invalid);
+      Ok(PoolReserves {
+        token_address: utils::normalize_address(token_address).ok_or_else(invalid)?,
+        zil_reserve: reserve(0)?,
+        token_reserve: reserve(1)?,
+        fee_rate: None,
+      })
+    }).collect()
+  }
+
+  /// Parses the `output_after_fee` field of the zilswap contract's state, the
+  /// part of each input in basis points that is swapped after the fee, e.g. 9970
+  /// for a 0.3% fee. Returns `None` if the field is missing or invalid.
+  pub fn fee_rate_from_state(state: &Value) -> Option<BigDecimal> {
+    let output_after_fee = state.get("output_after_fee")?.as_str()?.parse::<u32>().ok()?;
+    if output_after_fee > 10_000 {
+      return None
+    }
+    Some(BigDecimal::new((10_000 - output_after_fee).into(), 4))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_endpoints_start_from_the_last_that_responded() {
+    let endpoints = RpcEndpoints::new(&[String::from("http://a"), String::from("http://b"), String::from("http://c")]);
+    assert_eq!(endpoints.in_order().collect::<Vec<_>>(), vec![(0, "http://a"), (1, "http://b"), (2, "http://c")]);
+
+    endpoints.responded(1);
+    assert_eq!(endpoints.in_order().collect::<Vec<_>>(), vec![(1, "http://b"), (2, "http://c"), (0, "http://a")]);
+
+    // clones share the current endpoint
+    endpoints.clone().responded(2);
+    assert_eq!(endpoints.in_order().next(), Some((2, "http://c")));
+  }
+
+  #[test]
+  fn test_pool_reserves_from_state() {
+    let state = json!({
+      "pools": {
+        "0x0d21c1901a06abee40d8177f95171c8c63abdc31": {
+          "argtypes": [],
+          "arguments": ["1000000", "2500"],
+          "constructor": "Pool",
+        },
+      },
+    });
+    let reserves = PoolReserves::from_state(&state).unwrap();
+    assert_eq!(reserves.len(), 1);
+    assert_eq!(reserves[0].token_address, "zil1p5suryq6q647usxczale29cu3336hhp376c627");
+    assert_eq!(reserves[0].zil_reserve, BigDecimal::from(1_000_000));
+    assert_eq!(reserves[0].token_reserve, BigDecimal::from(2500));
+    assert_eq!(reserves[0].fee_rate, None);
+
+    let state = json!({ "pools": { "0x0d21c1901a06abee40d8177f95171c8c63abdc31": { "arguments": ["1000000"] } } });
+    assert!(PoolReserves::from_state(&state).is_err());
+  }
+
+  #[test]
+  fn test_fee_rate_from_state() {
+    let fee_rate = PoolReserves::fee_rate_from_state(&json!({ "output_after_fee": "9970" }));
+    assert_eq!(fee_rate, Some(BigDecimal::from_str("0.003").unwrap()));
+    let fee_rate = PoolReserves::fee_rate_from_state(&json!({ "output_after_fee": "10000" }));
+    assert_eq!(fee_rate, Some(BigDecimal::from(0)));
+    for state in [json!({}), json!({ "output_after_fee": "10001" }), json!({ "output_after_fee": "-1" }), json!({ "output_after_fee": 9970 })] {
+      assert_eq!(PoolReserves::fee_rate_from_state(&state), None);
+    }
   }
 }
@@ -12,6 +12,7 @@ table! {
         block_height -> Int4,
         block_timestamp -> Timestamp,
         num_txs -> Int4,
+        block_hash -> Nullable<Varchar>,
     }
 }
 
@@ -26,6 +27,8 @@ table! {
         distributor_address -> Varchar,
         epoch_number -> Int4,
         amount -> Numeric,
+        fee -> Nullable<Numeric>,
+        success -> Bool,
     }
 }
 
@@ -41,6 +44,22 @@ table! {
     }
 }
 
+table! {
+    failed_events (id) {
+        id -> Uuid,
+        transaction_hash -> Varchar,
+        event_sequence -> Int4,
+        block_height -> Int4,
+        block_timestamp -> Timestamp,
+        contract_address -> Varchar,
+        initiator_address -> Varchar,
+        event_name -> Varchar,
+        params -> Jsonb,
+        error -> Text,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     liquidity_changes (id) {
         id -> Uuid,
@@ -53,6 +72,20 @@ table! {
         change_amount -> Numeric,
         token_amount -> Numeric,
         zil_amount -> Numeric,
+        success -> Bool,
+    }
+}
+
+table! {
+    reserves (id) {
+        id -> Uuid,
+        transaction_hash -> Varchar,
+        event_sequence -> Int4,
+        block_height -> Int4,
+        block_timestamp -> Timestamp,
+        token_address -> Varchar,
+        zil_reserve -> Numeric,
+        token_reserve -> Numeric,
     }
 }
 
@@ -68,6 +101,8 @@ table! {
         token_amount -> Numeric,
         zil_amount -> Numeric,
         is_sending_zil -> Bool,
+        fee -> Nullable<Numeric>,
+        success -> Bool,
     }
 }
 
@@ -101,6 +136,8 @@ allow_tables_to_appear_in_same_query!(
     block_syncs,
     claims,
     distributions,
+    failed_events,
     liquidity_changes,
+    reserves,
     swaps,
 );
@@ -0,0 +1,126 @@
+//! Helpers for tests that need a database. These tests are ignored by default,
+//! and run against the database at `TEST_DATABASE_URL` with `cargo test -- --ignored`.
+
+use bigdecimal::{BigDecimal};
+use bech32::{ToBase32};
+use chrono::{NaiveDateTime};
+use diesel::{Connection, PgConnection};
+use std::sync::Once;
+use std::time::Duration;
+
+use crate::constants::{Network};
+use crate::db;
+use crate::models;
+use crate::worker::{WorkerConfig};
+
+static MIGRATIONS: Once = Once::new();
+
+/// Connects to the test database, running migrations on first use. The connection
+/// is in a test transaction, so that nothing a test does is committed.
+pub fn test_conn() -> PgConnection {
+  let connspec = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL env var missing.");
+  let conn = PgConnection::establish(&connspec).expect("Failed to connect to test database.");
+  MIGRATIONS.call_once(|| crate::embedded_migrations::run(&conn).expect("failed to run migrations."));
+  conn.begin_test_transaction().expect("Failed to begin test transaction.");
+  conn
+}
+
+/// A random bech32 address. Tests use fresh addresses so that they do not see each other's rows.
+pub fn random_address() -> String {
+  bech32::encode("zil", rand::random::<[u8; 20]>().to_base32()).unwrap()
+}
+
+/// A random transaction hash, in the form that transactions are stored in.
+pub fn random_tx_hash() -> String {
+  format!("0x{}", hex::encode(rand::random::<[u8; 32]>()))
+}
+
+/// A random block height, far above those of real blocks, for tests that sync blocks.
+pub fn random_block_height() -> i32 {
+  1_000_000_000 + rand::random::<u16>() as i32 * 1_000
+}
+
+/// The timestamp given to test events at the given block height, 30s apart.
+pub fn block_timestamp(block_height: i32) -> NaiveDateTime {
+  NaiveDateTime::from_timestamp(1_600_000_000 + block_height as i64 * 30, 0)
+}
+
+/// A worker config for the mainnet zilswap contract with a local node that is not running.
+pub fn worker_config() -> WorkerConfig {
+  WorkerConfig::new(Network::MainNet, "0x459cb2d3baf7e61cfbd5fe362f289ae92b2babb0", vec![], 0, vec![String::from("http://127.0.0.1:4201")], Duration::from_secs(1), 0, 1, 1)
+}
+
+/// Inserts a synced block.
+pub fn seed_block_sync(conn: &PgConnection, block_height: i32, block_hash: &str) {
+  db::insert_block_sync(conn, models::NewBlockSync {
+    block_height: &block_height,
+    block_timestamp: &block_timestamp(block_height),
+    num_txs: &0,
+    block_hash,
+  }).unwrap();
+}
+
+/// Inserts a successful swap without a fee.
+pub fn seed_swap(
+  conn: &PgConnection,
+  pool: &str,
+  initiator: &str,
+  block_height: i32,
+  zil_amount: i64,
+  token_amount: i64,
+  is_sending_zil: bool,
+) {
+  db::insert_swap(models::NewSwap {
+    transaction_hash: &random_tx_hash(),
+    event_sequence: &0,
+    block_height: &block_height,
+    block_timestamp: &block_timestamp(block_height),
+    initiator_address: initiator,
+    token_address: pool,
+    token_amount: &BigDecimal::from(token_amount),
+    zil_amount: &BigDecimal::from(zil_amount),
+    is_sending_zil: &is_sending_zil,
+    fee: None,
+    success: &true,
+  }, conn).unwrap();
+}
+
+/// Inserts a successful liquidity change, which adds liquidity if
+/// `change_amount` is positive and removes it otherwise.
+pub fn seed_liquidity_change(
+  conn: &PgConnection,
+  pool: &str,
+  initiator: &str,
+  block_height: i32,
+  change_amount: i64,
+) {
+  let amount = BigDecimal::from(change_amount.abs());
+  db::insert_liquidity_change(models::NewLiquidityChange {
+    transaction_hash: &random_tx_hash(),
+    event_sequence: &0,
+    block_height: &block_height,
+    block_timestamp: &block_timestamp(block_height),
+    initiator_address: initiator,
+    token_address: pool,
+    change_amount: &BigDecimal::from(change_amount),
+    token_amount: &amount,
+    zil_amount: &amount,
+    success: &true,
+  }, conn).unwrap();
+}
+
+/// Inserts a successful claim for an epoch of a distributor.
+pub fn seed_claim(conn: &PgConnection, distributor: &str, initiator: &str, block_height: i32, epoch_number: i32, amount: i64) {
+  db::insert_claim(models::NewClaim {
+    transaction_hash: &random_tx_hash(),
+    event_sequence: &0,
+    block_height: &block_height,
+    block_timestamp: &block_timestamp(block_height),
+    initiator_address: initiator,
+    distributor_address: distributor,
+    epoch_number: &epoch_number,
+    amount: &BigDecimal::from(amount),
+    fee: None,
+    success: &true,
+  }, conn).unwrap();
+}
@@ -0,0 +1,115 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::responses::ZilStreamToken;
+use crate::utils;
+
+/// Seconds between refreshes of the token list.
+const REFRESH_INTERVAL: u64 = 600;
+
+/// Token metadata from ZilStream, by bech32 address. Clones share the same
+/// list, which is empty until it is first fetched.
+#[derive(Clone, Default)]
+pub struct TokenList {
+  tokens: Arc<RwLock<HashMap<String, ZilStreamToken>>>,
+}
+
+/// Tokens that may be quoted, by bech32 address. All tokens may be quoted if it is empty.
+#[derive(Clone, Debug, Default)]
+pub struct TokenAllowlist {
+  tokens: HashSet<String>,
+}
+
+/// Name and symbol of a token, embedded in responses with `include=token_meta`.
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenMeta {
+  pub name: String,
+  pub symbol: String,
+  pub decimals: u32,
+}
+
+/// A record with the metadata of its token, which is null if unknown.
+#[derive(Debug, Serialize)]
+pub struct WithTokenMeta<T> {
+  #[serde(flatten)]
+  pub record: T,
+  pub token_meta: Option<TokenMeta>,
+}
+
+impl TokenList {
+  /// All known tokens, sorted by symbol.
+  pub fn all(&self) -> Vec<ZilStreamToken> {
+    let mut tokens: Vec<ZilStreamToken> = self.tokens.read().unwrap().values().cloned().collect();
+    tokens.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+    tokens
+  }
+
+  pub fn meta(&self, address: &str) -> Option<TokenMeta> {
+    self.tokens.read().unwrap().get(address).map(|t| TokenMeta {
+      name: t.name.clone(),
+      symbol: t.symbol.clone(),
+      decimals: t.decimals,
+    })
+  }
+
+  pub fn with_meta<T>(&self, address: &str, record: T) -> WithTokenMeta<T> {
+    WithTokenMeta { token_meta: self.meta(address), record }
+  }
+
+  async fn refresh(&self, client: &reqwest::Client, url: &str) -> Result<usize, reqwest::Error> {
+    let tokens: Vec<ZilStreamToken> = client.get(url).send().await?.error_for_status()?.json().await?;
+    let count = tokens.len();
+    *self.tokens.write().unwrap() = tokens.into_iter().map(|t| (t.address_bech32.clone(), t)).collect();
+    Ok(count)
+  }
+
+  /// Fetches the token list now and then every `REFRESH_INTERVAL` seconds.
+  /// The last fetched list is kept if ZilStream cannot be reached.
+  pub fn start_refresh(&self, url: String, timeout: Duration) {
+    let tokens = self.clone();
+    actix_web::rt::spawn(async move {
+      let client = reqwest::Client::builder()
+        .timeout(timeout)
+        .build()
+        .expect("failed to build http client");
+      loop {
+        match tokens.refresh(&client, &url).await {
+          Ok(count) => debug!("Fetched {} tokens from {}", count, url),
+          Err(e) => warn!("Failed to fetch token list from {}: {}", url, e),
+        }
+        actix::clock::delay_for(Duration::from_secs(REFRESH_INTERVAL)).await;
+      }
+    });
+  }
+}
+
+impl TokenAllowlist {
+  /// Accepts bech32 or hex addresses. Returns the first invalid address as the error.
+  pub fn new(addresses: &[String]) -> Result<TokenAllowlist, String> {
+    let tokens = addresses.iter()
+      .map(|address| utils::normalize_address(address).ok_or_else(|| address.clone()))
+      .collect::<Result<HashSet<String>, String>>()?;
+    Ok(TokenAllowlist { tokens })
+  }
+
+  pub fn allows(&self, address: &str) -> bool {
+    self.tokens.is_empty() || self.tokens.contains(address)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_allowlist() {
+    let allowlist = TokenAllowlist::new(&[String::from("0x0d21c1901a06abee40d8177f95171c8c63abdc31")]).unwrap();
+    assert!(allowlist.allows("zil1p5suryq6q647usxczale29cu3336hhp376c627"));
+    assert!(!allowlist.allows("zil1z5l74hwy3pc3pr3gdh3nqju4jlyp0dzkhq2f5y"));
+
+    assert!(TokenAllowlist::new(&[]).unwrap().allows("zil1z5l74hwy3pc3pr3gdh3nqju4jlyp0dzkhq2f5y"));
+    assert_eq!(TokenAllowlist::new(&[String::from("zil1invalid")]).unwrap_err(), "zil1invalid");
+  }
+}
@@ -1,20 +1,53 @@
-use bigdecimal::{BigDecimal, Zero};
+use bech32::{FromBase32, ToBase32};
+use bigdecimal::{BigDecimal};
 use num_bigint::BigInt;
+use std::error::Error;
+use std::fmt;
 
+/// Rounds the given number towards zero, keeping `round_digits` digits after the decimal point.
 pub fn round_down(bd: BigDecimal, round_digits: i64) -> BigDecimal {
   let (bigint, decimal_part_digits) = bd.as_bigint_and_exponent();
   let need_to_round_digits = decimal_part_digits - round_digits;
-  if round_digits >= 0 && need_to_round_digits <= 0 {
-      return bd.clone();
+  if need_to_round_digits <= 0 {
+      return bd;
   }
-  let mut number = bigint.clone();
-  if number < BigInt::zero() {
-      number = -number;
+  let mut divisor = BigInt::from(1);
+  for _ in 0..need_to_round_digits {
+      divisor *= 10;
   }
-  for _ in 0..(need_to_round_digits - 1) {
-      number /= 10;
+  // integer division truncates towards zero for both signs
+  BigDecimal::new(bigint / divisor, round_digits)
+}
+
+/// Decodes a bech32 (`zil1...`) or hex (`0x...`) address.
+/// Returns `None` if the address is invalid.
+fn address_bytes(address: &str) -> Option<Vec<u8>> {
+  let bytes = match address.strip_prefix("0x").or_else(|| address.strip_prefix("0X")) {
+    Some(hex_address) => hex::decode(hex_address).ok()?,
+    None => {
+      let (hrp, data) = bech32::decode(address).ok()?;
+      if hrp != "zil" {
+        return None
+      }
+      Vec::<u8>::from_base32(&data).ok()?
+    },
+  };
+  if bytes.len() != 20 {
+    return None
   }
-  bd.with_scale(round_digits)
+  Some(bytes)
+}
+
+/// Converts a bech32 or hex address to the bech32 form that user
+/// and pool addresses are stored in.
+pub fn normalize_address(address: &str) -> Option<String> {
+  bech32::encode("zil", address_bytes(address)?.to_base32()).ok()
+}
+
+/// Converts a bech32 or hex address to the lowercase hex form that
+/// distributor contract addresses are stored in.
+pub fn normalize_hex_address(address: &str) -> Option<String> {
+  address_bytes(address).map(|bytes| format!("0x{}", hex::encode(bytes)))
 }
 
 #[derive(Debug)]
@@ -24,6 +57,40 @@ pub enum FetchError {
     Fetch(reqwest::Error),
     Parse(serde_json::Error),
     Database(diesel::result::Error),
+    // A json-rpc error (code, message) returned by the node.
+    Rpc(i64, String),
+    // A json-rpc batch response that does not match the batch request.
+    MalformedBatch(String),
+    // A block with a header that cannot be parsed.
+    MalformedBlock(String),
+    // An event that failed to persist, which is retried separately from fetches.
+    Persist(diesel::result::Error),
+}
+
+impl fmt::Display for FetchError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      FetchError::Fetch(e) => write!(f, "fetch error: {}", e),
+      FetchError::Parse(e) => write!(f, "parse error: {}", e),
+      FetchError::Database(e) => write!(f, "database error: {}", e),
+      FetchError::Rpc(code, message) => write!(f, "rpc error {}: {}", code, message),
+      FetchError::MalformedBatch(details) => write!(f, "malformed batch: {}", details),
+      FetchError::MalformedBlock(details) => write!(f, "malformed block: {}", details),
+      FetchError::Persist(e) => write!(f, "persist error: {}", e),
+    }
+  }
+}
+
+impl Error for FetchError {
+  fn source(&self) -> Option<&(dyn Error + 'static)> {
+    match self {
+      FetchError::Fetch(e) => Some(e),
+      FetchError::Parse(e) => Some(e),
+      FetchError::Database(e) => Some(e),
+      FetchError::Persist(e) => Some(e),
+      _ => None,
+    }
+  }
 }
 
 impl From<reqwest::Error> for FetchError {
@@ -43,3 +110,28 @@ impl From<diesel::result::Error> for FetchError {
     FetchError::Database(err)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::str::FromStr;
+
+  fn round(number: &str, round_digits: i64) -> BigDecimal {
+    round_down(BigDecimal::from_str(number).unwrap(), round_digits)
+  }
+
+  #[test]
+  fn test_round_down() {
+    assert_eq!(round("2.9", 0), BigDecimal::from(2));
+    assert_eq!(round("-2.9", 0), BigDecimal::from(-2));
+    assert_eq!(round("3", 0), BigDecimal::from(3));
+    assert_eq!(round("3.00", 0), BigDecimal::from(3));
+    assert_eq!(round("-3", 0), BigDecimal::from(-3));
+    assert_eq!(round("1.9999", 0), BigDecimal::from(1));
+    assert_eq!(round("1.9999", 2), BigDecimal::from_str("1.99").unwrap());
+    assert_eq!(round("0.125", 2), BigDecimal::from_str("0.12").unwrap());
+    assert_eq!(round("-1.9999", 0), BigDecimal::from(-1));
+    assert_eq!(round("-0.125", 2), BigDecimal::from_str("-0.12").unwrap());
+    assert_eq!(round("1.5", 3), BigDecimal::from_str("1.5").unwrap());
+  }
+}
@@ -2,21 +2,29 @@ use actix::prelude::*;
 use bech32::{encode, ToBase32};
 use bigdecimal::{BigDecimal};
 use chrono::{NaiveDateTime};
-use diesel::PgConnection;
+use diesel::{Connection, PgConnection};
 use diesel::r2d2::{Pool, ConnectionManager};
 use hex;
+use rand::Rng;
 use ring::{digest};
-use serde_json::Value;
+use serde::{Serialize};
+use serde_json::{json, Value};
 use std::time::{Duration};
 use std::convert::TryInto;
 use std::ops::Neg;
 use std::cmp::{max, min};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use uuid::Uuid;
 
 use crate::db;
+use crate::metrics;
 use crate::models;
 use crate::utils;
-use crate::rpc::{ZilliqaClient, TxResult};
+use crate::rpc::{ZilliqaClient, BlockResult, BlockTxsPage, TxResult, TxTransition};
 use crate::constants::{Event, Network};
 
 #[derive(Clone)]
@@ -25,23 +33,112 @@ pub struct WorkerConfig {
   contract_hash: String,
   distributor_contract_hashes: Vec<String>,
   min_sync_height: u32,
-  rpc_url: String,
+  rpc_urls: Vec<String>,
+  rpc_timeout: Duration,
+  confirmation_depth: u32,
+  worker_threads: usize,
+  batch_size: u32,
 }
 
 impl WorkerConfig {
+  #[allow(clippy::too_many_arguments)]
   pub fn new(
     network: Network,
     contract_hash: &str,
     distributor_contract_hashes: Vec<&str>,
     min_sync_height: u32,
-    rpc_url: String,
+    rpc_urls: Vec<String>,
+    rpc_timeout: Duration,
+    confirmation_depth: u32,
+    worker_threads: usize,
+    batch_size: u32,
   ) -> Self {
     Self {
       network: network.clone(),
       contract_hash: contract_hash.to_owned(),
       distributor_contract_hashes: distributor_contract_hashes.into_iter().map(|h| h.to_owned()).collect(),
       min_sync_height,
-      rpc_url,
+      rpc_urls,
+      rpc_timeout,
+      confirmation_depth,
+      worker_threads: max(worker_threads, 1),
+      batch_size: max(batch_size, 1),
+    }
+  }
+}
+
+/// Seconds between polls of the chain for new blocks.
+const POLL_INTERVAL: u64 = 20;
+
+/// Seconds between checks for blocks missing from the synced range.
+const BACKFILL_INTERVAL: u64 = 3600;
+
+/// Number of blocks below the last synced block that are not backfilled,
+/// as they may still be queued for processing.
+const BACKFILL_MARGIN: u32 = 100;
+
+/// Hash returned by the node for blocks it does not have yet.
+const EMPTY_BLOCK_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Number of attempts at processing a block before an unparseable
+/// block timestamp is replaced by that of the previous block.
+const MAX_BLOCK_ATTEMPTS: u32 = 5;
+
+/// Number of times an event may fail to persist before it is recorded in
+/// `failed_events` and skipped. Counted apart from `MAX_BLOCK_ATTEMPTS`
+/// so that retries after node errors do not use up its attempts.
+const MAX_PERSIST_ATTEMPTS: u32 = 5;
+
+/// Set once shutdown starts; fetchers skip any jobs they receive after this.
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+/// Number of jobs currently being processed by fetchers.
+static RUNNING_JOBS: AtomicUsize = AtomicUsize::new(0);
+/// Number of jobs skipped by fetchers because of shutdown.
+static DROPPED_JOBS: AtomicUsize = AtomicUsize::new(0);
+
+/// Configuration and sync position of the worker, for operators.
+#[derive(Serialize)]
+pub struct WorkerStatus {
+  running: bool,
+  network: String,
+  contract_address: String,
+  distributor_addresses: Vec<String>,
+  min_sync_height: u32,
+  confirmation_depth: u32,
+  worker_threads: usize,
+  batch_size: u32,
+  poll_interval: u64,
+  last_sync_height: i32,
+  chain_height: i64,
+  sync_lag: i64,
+  jobs_in_flight: i64,
+  fetch_errors: u64,
+}
+
+impl WorkerConfig {
+  pub fn contract_address(&self) -> &str {
+    self.contract_hash.as_str()
+  }
+
+  /// Builds the worker status from this config, the last synced height and
+  /// the worker's metrics. The rpc urls are left out as they may contain credentials.
+  pub fn status(&self, running: bool, last_sync_height: i32) -> WorkerStatus {
+    let chain_height = metrics::WORKER_CHAIN_HEIGHT.get();
+    WorkerStatus {
+      running,
+      network: self.network.to_string(),
+      contract_address: self.contract_hash.clone(),
+      distributor_addresses: self.distributor_contract_hashes.clone(),
+      min_sync_height: self.min_sync_height,
+      confirmation_depth: self.confirmation_depth,
+      worker_threads: self.worker_threads,
+      batch_size: self.batch_size,
+      poll_interval: POLL_INTERVAL,
+      last_sync_height,
+      chain_height,
+      sync_lag: max(chain_height - last_sync_height as i64, 0),
+      jobs_in_flight: metrics::WORKER_JOBS_IN_FLIGHT.get(),
+      fetch_errors: metrics::WORKER_FETCH_ERRORS_TOTAL.get(),
     }
   }
 }
@@ -49,12 +146,39 @@ impl WorkerConfig {
 pub struct Coordinator{
   config: WorkerConfig,
   db_pool: Pool<ConnectionManager<PgConnection>>,
+  cache: Pool<redis::Client>,
   arbiter: Option<Addr<EventFetchActor>>,
+  scheduled_jobs: usize,
 }
 
 impl Coordinator {
-  pub fn new(config: WorkerConfig, db_pool: Pool<ConnectionManager<PgConnection>>) -> Self {
-    Coordinator { config, db_pool, arbiter: None }
+  pub fn new(config: WorkerConfig, db_pool: Pool<ConnectionManager<PgConnection>>, cache: Pool<redis::Client>) -> Self {
+    Coordinator { config, db_pool, cache, arbiter: None, scheduled_jobs: 0 }
+  }
+}
+
+/// Stops the worker: the coordinator stops dispatching jobs, and this waits up to
+/// the given timeout for jobs that are being processed to complete, then logs
+/// the height that the worker has synced up to.
+pub async fn shutdown(coordinator: Addr<Coordinator>, db_pool: Pool<ConnectionManager<PgConnection>>, timeout: Duration) {
+  let scheduled_jobs = coordinator.send(Shutdown).await.unwrap_or(0);
+
+  let start = std::time::Instant::now();
+  while RUNNING_JOBS.load(Ordering::SeqCst) > 0 && start.elapsed() < timeout {
+    actix::clock::delay_for(Duration::from_millis(100)).await;
+  }
+
+  let running_jobs = RUNNING_JOBS.load(Ordering::SeqCst);
+  if running_jobs > 0 {
+    warn!("Worker shutdown timed out with {} jobs still running.", running_jobs);
+  }
+  info!("Worker stopped, dropped {} pending jobs.", scheduled_jobs + DROPPED_JOBS.load(Ordering::SeqCst));
+
+  let last_sync_height = db_pool.get().map_err(|e| e.to_string())
+    .and_then(|conn| db::last_sync_height(&conn).map_err(|e| e.to_string()));
+  match last_sync_height {
+    Ok(height) => info!("Worker synced up to block {}.", height),
+    Err(e) => warn!("Failed to get last synced height: {}", e),
   }
 }
 
@@ -65,13 +189,21 @@ impl Actor for Coordinator {
     info!("Coordinator started up.");
     let config = self.config.clone();
     let db_pool = self.db_pool.clone();
+    let cache = self.cache.clone();
     let address = ctx.address();
-    info!("Coordinator starting sync with {}.", config.rpc_url);
+    info!("Coordinator starting sync with {} using {} fetchers.", config.rpc_urls.join(", "), config.worker_threads);
 
-    let arbiter = SyncArbiter::start(5, move || EventFetchActor::new(config.clone(), db_pool.clone(), address.clone()));
+    let zil_client = ZilliqaClient::new(&config.rpc_urls, config.rpc_timeout);
+    let arbiter = SyncArbiter::start(config.worker_threads, move || EventFetchActor::new(config.clone(), db_pool.clone(), cache.clone(), zil_client.clone(), address.clone()));
     let sync_start_block = std::env::var("FORCE_SYNC_HEIGHT").unwrap_or("0".to_string()).parse::<u32>().expect("invalid env value for FORCE_SYNC_HEIGHT");
+    metrics::WORKER_JOBS_IN_FLIGHT.inc();
     arbiter.do_send(Fetch::query_new_blocks(sync_start_block));
     self.arbiter = Some(arbiter);
+
+    let min_sync_height = self.config.min_sync_height;
+    ctx.run_interval(Duration::from_secs(BACKFILL_INTERVAL), move |_, ctx| {
+      ctx.notify(Backfill { from: min_sync_height + 1, to: u32::MAX });
+    });
   }
 
   fn stopped(&mut self, _: &mut Self::Context) {
@@ -87,10 +219,19 @@ impl Handler<NextFetch> for Coordinator {
 
   fn handle(&mut self, next_msg: NextFetch, ctx: &mut Context<Self>) -> Self::Result {
     let maybe_msg = next_msg.get_next();
+    if SHUTTING_DOWN.load(Ordering::SeqCst) {
+      if maybe_msg.is_some() {
+        DROPPED_JOBS.fetch_add(1, Ordering::SeqCst);
+      }
+      return
+    }
     match maybe_msg {
       Some(msg) => {
+        self.scheduled_jobs += 1;
         ctx.run_later(Duration::from_secs(next_msg.delay), move |worker, _| {
+          worker.scheduled_jobs -= 1;
           let arbiter = worker.arbiter.as_ref().unwrap();
+          metrics::WORKER_JOBS_IN_FLIGHT.inc();
           arbiter.do_send(msg);
         });
       },
@@ -99,6 +240,40 @@ impl Handler<NextFetch> for Coordinator {
   }
 }
 
+/// Define handler for `ReprocessFailedEvent` message,
+/// which queues a failed event to be persisted again.
+impl Handler<ReprocessFailedEvent> for Coordinator {
+  type Result = ();
+
+  fn handle(&mut self, msg: ReprocessFailedEvent, ctx: &mut Context<Self>) -> Self::Result {
+    self.handle(NextFetch::from(Fetch::reprocess_failed_event(msg.id), None), ctx)
+  }
+}
+
+/// Define handler for `Backfill` message, which queues
+/// a job to process any missing blocks in the given range.
+impl Handler<Backfill> for Coordinator {
+  type Result = ();
+
+  fn handle(&mut self, msg: Backfill, ctx: &mut Context<Self>) -> Self::Result {
+    self.handle(NextFetch::from(Fetch::backfill(msg.from, msg.to), None), ctx)
+  }
+}
+
+/// Define handler for `Shutdown` message, which stops the
+/// coordinator along with any jobs it has scheduled. Returns
+/// the number of scheduled jobs that were dropped.
+impl Handler<Shutdown> for Coordinator {
+  type Result = usize;
+
+  fn handle(&mut self, _msg: Shutdown, ctx: &mut Context<Self>) -> Self::Result {
+    info!("Coordinator shutting down.");
+    SHUTTING_DOWN.store(true, Ordering::SeqCst);
+    ctx.stop();
+    self.scheduled_jobs
+  }
+}
+
 #[derive(Debug, Clone)]
 struct ChainEvent {
   block_height: i32,
@@ -111,6 +286,37 @@ struct ChainEvent {
   params: Value,
 }
 
+impl ChainEvent {
+  fn failed<'a>(&'a self, error: &'a str) -> models::NewFailedEvent<'a> {
+    models::NewFailedEvent {
+      transaction_hash: &self.tx_hash,
+      event_sequence: &self.event_index,
+      block_height: &self.block_height,
+      block_timestamp: &self.block_timestamp,
+      contract_address: &self.contract_address,
+      initiator_address: &self.initiator_address,
+      event_name: &self.name,
+      params: &self.params,
+      error,
+    }
+  }
+}
+
+impl From<models::FailedEvent> for ChainEvent {
+  fn from(failed_event: models::FailedEvent) -> Self {
+    ChainEvent {
+      block_height: failed_event.block_height,
+      block_timestamp: failed_event.block_timestamp,
+      tx_hash: failed_event.transaction_hash,
+      event_index: failed_event.event_sequence,
+      contract_address: failed_event.contract_address,
+      initiator_address: failed_event.initiator_address,
+      name: failed_event.event_name,
+      params: failed_event.params,
+    }
+  }
+}
+
 #[derive(Clone)]
 struct QueryNewBlocksParams {
   prev_height: u32,
@@ -121,10 +327,30 @@ struct ProcessBlockParams {
   height: u32,
 }
 
+#[derive(Clone)]
+struct ProcessBlockRangeParams {
+  from: u32,
+  to: u32,
+}
+
+#[derive(Clone)]
+struct BackfillParams {
+  from: u32,
+  to: u32,
+}
+
+#[derive(Clone)]
+struct ReprocessFailedEventParams {
+  id: Uuid,
+}
+
 #[derive(Clone)]
 enum FetchJob {
-  QueryNewBlocksParams(QueryNewBlocksParams),
-  ProcessBlockParams(ProcessBlockParams),
+  QueryNewBlocks(QueryNewBlocksParams),
+  ProcessBlock(ProcessBlockParams),
+  ProcessBlockRange(ProcessBlockRangeParams),
+  Backfill(BackfillParams),
+  ReprocessFailedEvent(ReprocessFailedEventParams),
 }
 
 /// Define messages
@@ -132,6 +358,23 @@ enum FetchJob {
 /// is done within the handler itself.
 
 // Messages for coordinator
+#[derive(Message)]
+#[rtype(result = "usize")]
+pub struct Shutdown;
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Backfill {
+  pub from: u32,
+  pub to: u32,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ReprocessFailedEvent {
+  pub id: Uuid,
+}
+
 #[derive(Message)]
 #[rtype(result = "()")]
 struct NextFetch {
@@ -151,8 +394,16 @@ impl NextFetch {
     Self { msg: None, delay: 1 }
   }
 
-  fn retry(msg: &Fetch) -> Self {
-    Self { msg: Some(msg.clone()), delay: 5 }
+  /// Retries the given fetch with an exponentially increasing delay
+  /// (with jitter) based on how many times it has already been attempted.
+  /// Events failing to persist are counted apart from other errors.
+  fn retry(msg: &Fetch, error: &utils::FetchError) -> Self {
+    let mut msg = msg.clone();
+    match error {
+      utils::FetchError::Persist(_) => msg.persist_failures += 1,
+      _ => msg.attempt += 1,
+    }
+    Self { delay: retry_delay(msg.attempt + msg.persist_failures), msg: Some(msg) }
   }
 
   fn get_next(&self) -> Option<Fetch> {
@@ -160,45 +411,122 @@ impl NextFetch {
   }
 }
 
+const RETRY_BASE_DELAY: u64 = 5;
+const RETRY_MAX_DELAY: u64 = 300;
+
+/// Delay in seconds before the given retry attempt: doubles for each
+/// attempt up to `RETRY_MAX_DELAY`, randomized within the upper half
+/// so that failed fetches do not all retry at the same time.
+fn retry_delay(attempt: u32) -> u64 {
+  let exp = RETRY_BASE_DELAY.saturating_mul(1u64 << min(attempt.saturating_sub(1), 16));
+  let delay = min(exp, RETRY_MAX_DELAY);
+  delay / 2 + rand::thread_rng().gen_range(0, delay / 2 + 1)
+}
+
 // Messages for fetch actors
 #[derive(Message, Clone)]
 #[rtype(result = "()")]
 struct Fetch {
   job: FetchJob,
+  attempt: u32,
+  persist_failures: u32,
 }
 
 impl Fetch {
   fn query_new_blocks(prev_height: u32) -> Fetch {
-    let job = FetchJob::QueryNewBlocksParams(QueryNewBlocksParams{ prev_height });
-    Self { job }
+    let job = FetchJob::QueryNewBlocks(QueryNewBlocksParams{ prev_height });
+    Self { job, attempt: 0, persist_failures: 0 }
   }
   fn process_block(height: u32) -> Fetch {
-    let job = FetchJob::ProcessBlockParams(ProcessBlockParams{ height });
-    Self { job }
+    let job = FetchJob::ProcessBlock(ProcessBlockParams{ height });
+    Self { job, attempt: 0, persist_failures: 0 }
+  }
+  fn process_block_range(from: u32, to: u32) -> Fetch {
+    let job = FetchJob::ProcessBlockRange(ProcessBlockRangeParams{ from, to });
+    Self { job, attempt: 0, persist_failures: 0 }
+  }
+  fn backfill(from: u32, to: u32) -> Fetch {
+    let job = FetchJob::Backfill(BackfillParams{ from, to });
+    Self { job, attempt: 0, persist_failures: 0 }
+  }
+  fn reprocess_failed_event(id: Uuid) -> Fetch {
+    let job = FetchJob::ReprocessFailedEvent(ReprocessFailedEventParams{ id });
+    Self { job, attempt: 0, persist_failures: 0 }
   }
 }
 
 /// The actual fetch result
 type FetchResult = Result<NextFetch, utils::FetchError>;
 
-type PersistResult = Result<bool, diesel::result::Error>;
+type PersistResult = Result<bool, PersistError>;
+
+/// An error persisting an event. A database error may succeed when retried,
+/// while a malformed event never will.
+#[derive(Debug)]
+enum PersistError {
+  Database(diesel::result::Error),
+  Malformed(String),
+}
+
+impl fmt::Display for PersistError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      PersistError::Database(e) => write!(f, "database error: {}", e),
+      PersistError::Malformed(details) => write!(f, "malformed event: {}", details),
+    }
+  }
+}
+
+impl From<diesel::result::Error> for PersistError {
+  fn from(err: diesel::result::Error) -> PersistError {
+    PersistError::Database(err)
+  }
+}
 
 /// Define fetch actor
 struct EventFetchActor {
   config: WorkerConfig,
   coordinator: Addr<Coordinator>,
   zil_client: ZilliqaClient,
-  db_pool: Pool<ConnectionManager<PgConnection>>
+  db_pool: Pool<ConnectionManager<PgConnection>>,
+  cache: Pool<redis::Client>,
+  // (pool, address, timestamp) of liquidity changes persisted or rolled back in the current transaction
+  liquidity_changes: RefCell<Vec<(String, String, NaiveDateTime)>>,
 }
 
 impl EventFetchActor {
-  fn new(config: WorkerConfig, db_pool: Pool<ConnectionManager<PgConnection>>, coordinator: Addr<Coordinator>) -> Self {
-    let zil_client = ZilliqaClient::new(&config.rpc_url);
+  fn new(config: WorkerConfig, db_pool: Pool<ConnectionManager<PgConnection>>, cache: Pool<redis::Client>, zil_client: ZilliqaClient, coordinator: Addr<Coordinator>) -> Self {
     Self {
       zil_client,
       config,
       coordinator,
       db_pool,
+      cache,
+      liquidity_changes: RefCell::new(vec![]),
+    }
+  }
+
+  /// delete cached time-weighted liquidity affected by the liquidity changes
+  //  persisted or rolled back in a transaction, once it has been committed. deleting them
+  //  earlier would let requests cache the liquidity from before the changes.
+  fn invalidate_cache(&self, committed: bool) {
+    let liquidity_changes: Vec<(String, String, NaiveDateTime)> = self.liquidity_changes.borrow_mut().drain(..).collect();
+    if !committed || liquidity_changes.is_empty() {
+      return
+    }
+
+    let mut rconn = match self.cache.get() {
+      Ok(rconn) => rconn,
+      Err(e) => {
+        warn!("InvalidateCache: cache unavailable: {}", e);
+        return
+      }
+    };
+    for (pool, address, timestamp) in liquidity_changes {
+      match db::invalidate_time_weighted_liquidity(&mut rconn, &pool, &address, timestamp) {
+        Ok(count) => trace!("InvalidateCache: deleted {} entries of {} for {} at {}", count, pool, address, timestamp),
+        Err(e) => warn!("InvalidateCache: failed for {} of {} at {}: {}", address, pool, timestamp, e),
+      }
     }
   }
 
@@ -220,92 +548,277 @@ impl EventFetchActor {
 
             info!("QueryNewBlocks: last_sync_height {}", last_sync_height);
             let min_height = self.config.min_sync_height;
+            self.requeue_missing_blocks(&conn, min_height + 1, last_sync_height)?;
             max(last_sync_height, min_height)
           },
           false => in_prev_height,
         };
-        let chain_height = self.zil_client.get_latest_block()?;
+        let latest_height = self.zil_client.get_latest_block()?;
+        let synced_height: u32 = db::last_sync_height(&conn)?.try_into().expect("invalid last sync height");
+        metrics::set_sync_heights(synced_height, latest_height);
+
+        if synced_height > 0 {
+          self.requeue_replaced_blocks(&conn, synced_height)?;
+        }
+
+        // only sync blocks that have enough confirmations
+        let chain_height = latest_height.saturating_sub(self.config.confirmation_depth);
         if prev_height >= chain_height {
           return Ok(prev_height)
         }
 
         info!("QueryNewBlocks: sync {}/{}", prev_height + 1, chain_height);
 
-        let query_count: u32 = min(100, chain_height - prev_height).try_into().expect("invalid chain height");
+        let query_count = min(100, chain_height - prev_height);
         let last_height = prev_height + query_count - 1;
         trace!("QueryNewBlocks: from {} - {}", prev_height, last_height);
 
         let new_prev_height = last_height;
         let start_height = prev_height + 1;
 
-        for height in start_height..=last_height {
-          let msg = Fetch::process_block(height);
-          let next_msg = NextFetch::from(msg, None);
-          self.coordinator.do_send(next_msg)
+        if self.config.batch_size > 1 {
+          let mut from = start_height;
+          while from <= last_height {
+            let to = min(from + self.config.batch_size - 1, last_height);
+            let msg = Fetch::process_block_range(from, to);
+            self.coordinator.do_send(NextFetch::from(msg, None));
+            from = to + 1;
+          }
+        } else {
+          for height in start_height..=last_height {
+            let msg = Fetch::process_block(height);
+            let next_msg = NextFetch::from(msg, None);
+            self.coordinator.do_send(next_msg)
+          }
         }
         Ok(new_prev_height)
       })?;
 
     let msg = Fetch::query_new_blocks(new_prev_height);
-    Ok(NextFetch::from(msg, Some(20)))
+    Ok(NextFetch::from(msg, Some(POLL_INTERVAL)))
+  }
+
+  /// re-process the last synced blocks, as many as the confirmation depth,
+  //  that were replaced on chain. the last synced block is always checked.
+  fn requeue_replaced_blocks(&self, conn: &PgConnection, synced_height: u32) -> Result<(), utils::FetchError> {
+    let depth = max(self.config.confirmation_depth, 1);
+    let from = max(synced_height.saturating_sub(depth - 1), 1);
+    let block_syncs = db::get_block_syncs(conn, from as i32, synced_height as i32)?;
+    for block_syncs in block_syncs.chunks(self.config.batch_size as usize) {
+      let heights: Vec<u32> = block_syncs.iter().map(|b| b.block_height as u32).collect();
+      let blocks = match heights.as_slice() {
+        [height] => vec![self.zil_client.get_block(height)?],
+        heights => self.zil_client.get_blocks(heights)?,
+      };
+      for (block_sync, block) in block_syncs.iter().zip(blocks) {
+        if matches!(&block_sync.block_hash, Some(hash) if *hash != block.body.block_hash) {
+          warn!("QueryNewBlocks: block hash changed at {}, reprocessing", block_sync.block_height);
+          self.coordinator.do_send(NextFetch::from(Fetch::process_block(block_sync.block_height as u32), None));
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// find blocks missing in the given range, up to the last synced block
+  //  less `BACKFILL_MARGIN`, and queue them for processing.
+  fn backfill(&self, from: u32, to: u32) -> FetchResult {
+    trace!("Backfill: handle {} - {}", from, to);
+    let conn = self.db_pool.get().expect("couldn't get db connection from pool");
+
+    let synced_height: u32 = db::last_sync_height(&conn)?.try_into().expect("invalid last sync height");
+    let to = min(to, synced_height.saturating_sub(BACKFILL_MARGIN));
+    self.requeue_missing_blocks(&conn, from, to)?;
+
+    Ok(NextFetch::empty())
+  }
+
+  /// Queues a `ProcessBlock` job for every block between `from` and `to`
+  //  that has no block sync, e.g. because its job was lost on an unclean restart.
+  fn requeue_missing_blocks(&self, conn: &PgConnection, from: u32, to: u32) -> Result<(), utils::FetchError> {
+    if from > to {
+      return Ok(())
+    }
+    let missing_heights = db::find_missing_block_heights(conn, from as i32, to as i32)?;
+    if !missing_heights.is_empty() {
+      warn!("QueryNewBlocks: found {} missing blocks between {} and {}, requeuing", missing_heights.len(), from, to);
+    }
+    for height in missing_heights {
+      let msg = Fetch::process_block(height as u32);
+      self.coordinator.do_send(NextFetch::from(msg, None));
+    }
+    Ok(())
   }
 
   /// query one single block from chain based on given height.
   //  list all transactions on block and process all one by one.
-  fn process_block(&self, height: u32) -> FetchResult {
+  fn process_block(&self, height: u32, attempt: u32, persist_failures: u32) -> FetchResult {
     trace!("ProcessBlock: handle {}", height);
     let conn = self.db_pool.get().expect("couldn't get db connection from pool");
 
-    conn.build_transaction()
+    let result = conn.build_transaction()
       .read_write()
       .run::<_, utils::FetchError, _>(|| {
         let block = self.zil_client.get_block(&height)?;
 
-        if block.body.block_hash == "0000000000000000000000000000000000000000000000000000000000000000" {
+        if block.body.block_hash == EMPTY_BLOCK_HASH {
           trace!("ProcessBlock: block not available on node {}", height);
           return Ok(())
         }
 
-        let block_height = block.header.block_num.parse::<u32>().expect("invalid block height");
-        let timestamp = block.header.timestamp.parse::<i64>().expect("invalid block timestamp");
-        let timestamp_seconds = timestamp / 1000;
-        let block_timestamp = chrono::NaiveDateTime::from_timestamp(timestamp_seconds / 1000, (timestamp_seconds % 1000).try_into().unwrap());
-        let num_txs = block.header.num_txns as i32;
-
-        let new_block_sync = models::NewBlockSync {
-          block_height: &(block_height as i32),
-          block_timestamp: &block_timestamp,
-          num_txs: &num_txs,
-        };
-
+        let mut txs = vec![];
         if block.header.num_txns > 0 {
-          let txs_result = self.zil_client.get_block_txs(&height)?;
-          let block_txs = txs_result.list();
+          let txs_page = self.zil_client.get_block_txs_paged(&height, 0)?;
+          let block_txs = self.get_block_txs(height, txs_page)?;
 
           trace!("ProcessBlock: block {} found txs {}", height, block_txs.len());
-          for tx_hash in block_txs {
-            self.process_tx(&conn, tx_hash, &new_block_sync)?;
-          }
+          let tx_results = self.get_transactions(&block_txs)?;
+          txs = block_txs.into_iter().zip(tx_results).collect();
         }
 
-        db::insert_block_sync(&conn, new_block_sync)?;
-        debug!("ProcessBlock: block complete {} {}", &block_height, &num_txs);
-        Ok(())
-      })?;
+        self.sync_block(&conn, &block, txs, attempt, persist_failures)
+      });
+    self.invalidate_cache(result.is_ok());
+    result?;
 
     Ok(NextFetch::empty())
   }
 
-  /// query one single block from chain based on given height.
-  //  list all transactions on block and queue all with `SaveTx` job.
-  fn process_tx(&self, conn: &PgConnection, tx_hash: String, block: &models::NewBlockSync) -> Result<(), utils::FetchError> {
-    
-    trace!("ProcessTx: handle {} {}", block.block_height, tx_hash);
+  /// query a range of consecutive blocks from chain with batched rpc calls
+  //  and process them in a single transaction. if a block is not yet available
+  //  on the node, it and the blocks after it are queued again as a new range.
+  fn process_block_range(&self, from: u32, to: u32, attempt: u32, persist_failures: u32) -> FetchResult {
+    trace!("ProcessBlockRange: handle {} - {}", from, to);
+    let conn = self.db_pool.get().expect("couldn't get db connection from pool");
 
-    let tx_result = self.zil_client.get_transaction(&tx_hash)?;
-    if !tx_result.receipt.success {
-      return Ok(());
+    let processed = conn.build_transaction()
+      .read_write()
+      .run::<_, utils::FetchError, _>(|| {
+        let heights: Vec<u32> = (from..=to).collect();
+        let blocks: Vec<BlockResult> = self.zil_client.get_blocks(&heights)?
+          .into_iter()
+          .take_while(|block| block.body.block_hash != EMPTY_BLOCK_HASH)
+          .collect();
+
+        let tx_heights: Vec<u32> = blocks.iter()
+          .filter(|block| block.header.num_txns > 0)
+          .map(|block| block.header.block_num.parse::<u32>().expect("invalid block height"))
+          .collect();
+        let block_txs: Vec<Vec<String>> = self.zil_client.get_blocks_txs(&tx_heights)?
+          .into_iter()
+          .zip(tx_heights.iter())
+          .map(|(txs_page, height)| self.get_block_txs(*height, txs_page))
+          .collect::<Result<_, _>>()?;
+        let tx_results = self.get_transactions(&block_txs.concat())?;
+        trace!("ProcessBlockRange: blocks {} found txs {}", blocks.len(), tx_results.len());
+
+        let mut tx_results = tx_results.into_iter();
+        let mut txs_by_height: HashMap<u32, Vec<(String, TxResult)>> = HashMap::new();
+        for (height, tx_hashes) in tx_heights.into_iter().zip(block_txs) {
+          let txs = tx_hashes.into_iter().zip(tx_results.by_ref()).collect();
+          txs_by_height.insert(height, txs);
+        }
+
+        for block in blocks.iter() {
+          let height = block.header.block_num.parse::<u32>().expect("invalid block height");
+          self.sync_block(&conn, block, txs_by_height.remove(&height).unwrap_or_default(), attempt, persist_failures)?;
+        }
+        Ok(blocks.len() as u32)
+      });
+    self.invalidate_cache(processed.is_ok());
+    let processed = processed?;
+
+    if from + processed <= to {
+      trace!("ProcessBlockRange: block not available on node {}", from + processed);
+      let msg = Fetch::process_block_range(from + processed, to);
+      return Ok(NextFetch::from(msg, Some(POLL_INTERVAL)))
+    }
+
+    Ok(NextFetch::empty())
+  }
+
+  /// get the hashes of all transactions of a block given its first page
+  //  of transactions, fetching the remaining pages for large blocks.
+  fn get_block_txs(&self, height: u32, first_page: BlockTxsPage) -> Result<Vec<String>, utils::FetchError> {
+    let mut block_txs = first_page.transactions.list();
+    for page in 1..first_page.num_pages {
+      trace!("ProcessBlock: block {} fetching txs page {}/{}", height, page + 1, first_page.num_pages);
+      block_txs.extend(self.zil_client.get_block_txs_paged(&height, page)?.transactions.list());
     }
+    Ok(block_txs)
+  }
+
+  /// get transactions with a single batched rpc call, falling back to
+  //  one call per transaction if the node returns a malformed batch response.
+  fn get_transactions(&self, tx_hashes: &[String]) -> Result<Vec<TxResult>, utils::FetchError> {
+    match self.zil_client.get_transactions(tx_hashes) {
+      Err(e @ utils::FetchError::Parse(_)) | Err(e @ utils::FetchError::MalformedBatch(_)) => {
+        warn!("GetTransactions: malformed batch response, fetching sequentially: {}", e);
+        tx_hashes.iter().map(|tx_hash| self.zil_client.get_transaction(tx_hash)).collect()
+      },
+      result => result,
+    }
+  }
+
+  /// persist the events of a block's transactions and mark it as synced.
+  //  a previously synced block that has since been reorganized is rolled back first.
+  fn sync_block(&self, conn: &PgConnection, block: &BlockResult, txs: Vec<(String, TxResult)>, attempt: u32, persist_failures: u32) -> Result<(), utils::FetchError> {
+    let block_height = block.header.block_num.parse::<u32>().expect("invalid block height");
+    let block_timestamp = match parse_block_timestamp(&block.header.timestamp) {
+      Some(block_timestamp) => block_timestamp,
+      None if attempt + 1 < MAX_BLOCK_ATTEMPTS => {
+        return Err(utils::FetchError::MalformedBlock(format!("invalid timestamp {:?} for block {}", block.header.timestamp, block_height)))
+      },
+      None => {
+        let block_timestamp = self.previous_block_timestamp(conn, block_height)?;
+        warn!("ProcessBlock: invalid timestamp {:?} for block {}, using previous block's {}", block.header.timestamp, block_height, block_timestamp);
+        block_timestamp
+      },
+    };
+    let num_txs = block.header.num_txns as i32;
+
+    // roll back a previously synced block that has since been reorganized
+    if let Some(block_sync) = db::get_block_sync(conn, block_height as i32)? {
+      if matches!(&block_sync.block_hash, Some(hash) if *hash != block.body.block_hash) {
+        warn!("ProcessBlock: block {} reorganized, rolling back", block_height);
+        let liquidity_changes = db::rollback_block(conn, block_height as i32)?;
+        self.liquidity_changes.borrow_mut().extend(liquidity_changes);
+      }
+    }
+
+    let new_block_sync = models::NewBlockSync {
+      block_height: &(block_height as i32),
+      block_timestamp: &block_timestamp,
+      num_txs: &num_txs,
+      block_hash: &block.body.block_hash,
+    };
+
+    for (tx_hash, tx_result) in txs {
+      self.process_tx(conn, tx_hash, &tx_result, &new_block_sync, persist_failures)?;
+    }
+
+    db::insert_block_sync(conn, new_block_sync)?;
+    debug!("ProcessBlock: block complete {} {}", &block_height, &num_txs);
+    Ok(())
+  }
+
+  /// get the timestamp of the block before the given height, from the
+  //  synced blocks if it has been synced or else from the node.
+  fn previous_block_timestamp(&self, conn: &PgConnection, height: u32) -> Result<NaiveDateTime, utils::FetchError> {
+    let prev_height = height.saturating_sub(1);
+    if let Some(block_sync) = db::get_block_sync(conn, prev_height as i32)? {
+      return Ok(block_sync.block_timestamp)
+    }
+    let prev_block = self.zil_client.get_block(&prev_height)?;
+    parse_block_timestamp(&prev_block.header.timestamp)
+      .ok_or(utils::FetchError::MalformedBlock(format!("invalid timestamp {:?} for block {}", prev_block.header.timestamp, prev_height)))
+  }
+
+  /// process the events of one transaction.
+  //  malformed events, and events that keep failing to persist, are recorded as failed and skipped.
+  fn process_tx(&self, conn: &PgConnection, tx_hash: String, tx_result: &TxResult, block: &models::NewBlockSync, persist_failures: u32) -> Result<(), utils::FetchError> {
+    
+    trace!("ProcessTx: handle {} {}", block.block_height, tx_hash);
 
     let events = tx_result.receipt.events();
     let events_len = events.len();
@@ -327,7 +840,7 @@ impl EventFetchActor {
         None => continue,
       };
       match event_type {
-        Event::Minted | Event::Burnt | Event::Swapped => {
+        Event::Minted | Event::Burnt | Event::Swapped | Event::Synced => {
           if event.address != self.config.contract_hash { continue }
         },
         Event::Claimed => {
@@ -338,32 +851,163 @@ impl EventFetchActor {
       debug!("ProcessTx: event {} {} {}", &formatted_tx_hash, event_index, event._eventname);
 
       let chain_event = ChainEvent {
-        block_height: block.block_height.clone(),
-        block_timestamp: block.block_timestamp.clone(),
+        block_height: *block.block_height,
+        block_timestamp: *block.block_timestamp,
         tx_hash: formatted_tx_hash.clone(),
-        event_index: event_index.clone() as i32,
+        event_index: event_index as i32,
         contract_address: event.address.clone(),
         initiator_address: initiator_address.clone(),
         name: event._eventname.clone(),
         params: event.params.clone(),
       };
 
-      self.process_event(conn, &block, &tx_result, &chain_event)?;
+      self.persist_chain_event(conn, block, tx_result, &chain_event, persist_failures)?;
+    }
+
+    // swaps made through other contracts without a `Swapped` event are recovered from transitions
+    let has_swap_event = events.iter().any(|event| event.address == self.config.contract_hash && event._eventname == "Swapped");
+    if !has_swap_event {
+      let transitions = tx_result.receipt.transitions();
+      for (transition_index, transition) in transitions.iter().enumerate() {
+        if transition.msg._recipient != self.config.contract_hash { continue }
+        let params = match self.process_transition(&transitions[transition_index + 1..], transition) {
+          Some(params) => params,
+          None => continue,
+        };
+
+        // numbered after the events so as not to clash with them
+        let event_index = (events_len + transition_index) as i32;
+        debug!("ProcessTx: transition {} {} {}", &formatted_tx_hash, event_index, transition.msg._tag);
+
+        let chain_event = ChainEvent {
+          block_height: *block.block_height,
+          block_timestamp: *block.block_timestamp,
+          tx_hash: formatted_tx_hash.clone(),
+          event_index,
+          contract_address: self.config.contract_hash.clone(),
+          initiator_address: initiator_address.clone(),
+          name: String::from("Swapped"),
+          params,
+        };
+
+        self.persist_chain_event(conn, block, tx_result, &chain_event, persist_failures)?;
+      }
     }
     Ok(())
   }
 
+  /// persist an event within a savepoint. a malformed event, or an event that
+  //  has failed to persist `MAX_PERSIST_ATTEMPTS` times, is recorded as failed and skipped.
+  fn persist_chain_event(&self, conn: &PgConnection, block: &models::NewBlockSync, tx_result: &TxResult, chain_event: &ChainEvent, persist_failures: u32) -> Result<(), utils::FetchError> {
+    let result = conn.transaction(|| self.process_event(conn, block, tx_result, chain_event));
+    match result {
+      Ok(_) => {},
+      Err(PersistError::Database(e)) if persist_failures + 1 < MAX_PERSIST_ATTEMPTS => return Err(utils::FetchError::Persist(e)),
+      Err(e @ PersistError::Database(_)) => {
+        error!("ProcessTx: skipping event {} {} after {} attempts: {}", chain_event.tx_hash, chain_event.event_index, persist_failures + 1, e);
+        db::insert_failed_event(conn, chain_event.failed(&e.to_string()))?;
+      },
+      Err(e @ PersistError::Malformed(_)) => {
+        error!("ProcessTx: skipping event {} {}: {}", chain_event.tx_hash, chain_event.event_index, e);
+        db::insert_failed_event(conn, chain_event.failed(&e.to_string()))?;
+      },
+    }
+    Ok(())
+  }
+
+  /// get the params of the `Swapped` event equivalent to a swap transition
+  //  sent to the contract, using the transitions that follow it for the output
+  //  amount. only exact input swaps between zil and a token are recognized.
+  fn process_transition(&self, next_transitions: &[TxTransition], transition: &TxTransition) -> Option<Value> {
+    let contract = &self.config.contract_hash;
+    let pool = event_param(&transition.msg.params, "token_address")?.as_str()?;
+    match transition.msg._tag.as_str() {
+      "SwapExactZILForTokens" => {
+        let output = next_transitions.iter()
+          .find(|t| &t.addr == contract && t.msg._recipient == pool && t.msg._tag == "Transfer")?;
+        let token_amount = event_param(&output.msg.params, "amount")?.as_str()?;
+        Some(swapped_params(pool, &transition.addr, ("Zil", &transition.msg._amount), ("Token", token_amount)))
+      },
+      "SwapExactTokensForZIL" => {
+        let token_amount = event_param(&transition.msg.params, "token_amount")?.as_str()?;
+        let output = next_transitions.iter()
+          .find(|t| &t.addr == contract && t.msg._tag == "AddFunds")?;
+        Some(swapped_params(pool, &transition.addr, ("Token", token_amount), ("Zil", &output.msg._amount)))
+      },
+      _ => None,
+    }
+  }
+
+  /// persist an event that previously failed again, removing it from the
+  //  failed events if it succeeds, or updating its error otherwise.
+  fn reprocess_failed_event(&self, id: Uuid) -> FetchResult {
+    trace!("ReprocessFailedEvent: handle {}", id);
+    let conn = self.db_pool.get().expect("couldn't get db connection from pool");
+
+    let failed_event = match db::get_failed_event(&conn, id)? {
+      Some(failed_event) => failed_event,
+      None => {
+        warn!("ReprocessFailedEvent: failed event {} not found", id);
+        return Ok(NextFetch::empty())
+      }
+    };
+    let block_sync = match db::get_block_sync(&conn, failed_event.block_height)? {
+      Some(block_sync) => block_sync,
+      None => {
+        warn!("ReprocessFailedEvent: block {} not synced, skipping {}", failed_event.block_height, id);
+        return Ok(NextFetch::empty())
+      }
+    };
+    let tx_result = self.zil_client.get_transaction(&failed_event.transaction_hash.trim_start_matches("0x").to_owned())?;
+
+    let block_hash = block_sync.block_hash.unwrap_or_default();
+    let block = models::NewBlockSync {
+      block_height: &block_sync.block_height,
+      block_timestamp: &block_sync.block_timestamp,
+      num_txs: &block_sync.num_txs,
+      block_hash: &block_hash,
+    };
+    let chain_event = ChainEvent::from(failed_event);
+
+    let result = conn.transaction(|| {
+      self.process_event(&conn, &block, &tx_result, &chain_event)?;
+      db::delete_failed_event(&conn, id).map_err(PersistError::from)
+    });
+    self.invalidate_cache(result.is_ok());
+    match result {
+      Ok(_) => info!("ReprocessFailedEvent: reprocessed {} {}", chain_event.tx_hash, chain_event.event_index),
+      Err(e) => {
+        error!("ReprocessFailedEvent: failed again {} {}: {}", chain_event.tx_hash, chain_event.event_index, e);
+        db::insert_failed_event(&conn, chain_event.failed(&e.to_string()))?;
+      }
+    }
+
+    Ok(NextFetch::empty())
+  }
+
   /// poll chain events from database and persist events into database
   //  queue events for retry if failed.
   fn process_event(&self, conn: &PgConnection, block: &models::NewBlockSync, tx_result: &TxResult, event: &ChainEvent) -> PersistResult {
-    let event_type = Event::from_str(event.name.as_str()).unwrap();
+    let event_type = Event::from_str(event.name.as_str())
+      .ok_or_else(|| PersistError::Malformed(format!("unknown event {}", event.name)))?;
     let persist = match event_type {
       Event::Minted => persist_mint_event,
       Event::Burnt => persist_burn_event,
       Event::Swapped => persist_swap_event,
       Event::Claimed => persist_claim_event,
+      Event::Synced => persist_sync_event,
     };
-    persist(conn, &block, &tx_result, &event)
+    let inserted = persist(conn, &block, &tx_result, &event)?;
+
+    // liquidity changes invalidate cached time-weighted liquidity once committed
+    if inserted && (event_type == Event::Minted || event_type == Event::Burnt) {
+      let pool = event_param(&event.params, "pool").and_then(Value::as_str).and_then(utils::normalize_address);
+      let address = event_param(&event.params, "address").and_then(Value::as_str).and_then(utils::normalize_address);
+      if let (Some(pool), Some(address)) = (pool, address) {
+        self.liquidity_changes.borrow_mut().push((pool, address, event.block_timestamp));
+      }
+    }
+    Ok(inserted)
   }
 }
 
@@ -379,49 +1023,116 @@ impl Handler<Fetch> for EventFetchActor {
   type Result = ();
 
   fn handle(&mut self, msg: Fetch, _ctx: &mut SyncContext<Self>) -> () {
+    if SHUTTING_DOWN.load(Ordering::SeqCst) {
+      metrics::WORKER_JOBS_IN_FLIGHT.dec();
+      DROPPED_JOBS.fetch_add(1, Ordering::SeqCst);
+      return
+    }
+
+    RUNNING_JOBS.fetch_add(1, Ordering::SeqCst);
     let job = msg.job.clone();
     let result = match job {
-      FetchJob::QueryNewBlocksParams(params) => {
+      FetchJob::QueryNewBlocks(params) => {
         let prev_height = params.prev_height;
         self.query_new_blocks(prev_height)
       }
-      FetchJob::ProcessBlockParams(params) => {
+      FetchJob::ProcessBlock(params) => {
         let height = params.height;
-        self.process_block(height)
+        self.process_block(height, msg.attempt, msg.persist_failures)
+      }
+      FetchJob::ProcessBlockRange(params) => {
+        self.process_block_range(params.from, params.to, msg.attempt, msg.persist_failures)
+      }
+      FetchJob::Backfill(params) => {
+        self.backfill(params.from, params.to)
+      }
+      FetchJob::ReprocessFailedEvent(params) => {
+        self.reprocess_failed_event(params.id)
       }
     };
 
+    metrics::WORKER_JOBS_IN_FLIGHT.dec();
+    RUNNING_JOBS.fetch_sub(1, Ordering::SeqCst);
     match result {
       Ok(next_msg) => self.coordinator.do_send(next_msg),
       Err(e) => {
-        error!("{:#?}", e);
-        error!("Unhandled error while fetching, retrying in 10 seconds..");
-        self.coordinator.do_send(NextFetch::retry(&msg));
+        metrics::WORKER_FETCH_ERRORS_TOTAL.inc();
+        let next_msg = NextFetch::retry(&msg, &e);
+        error!("Unhandled error while fetching (attempt {}), retrying in {} seconds: {}", msg.attempt + msg.persist_failures + 1, next_msg.delay, e);
+        self.coordinator.do_send(next_msg);
       }
     }
   }
 }
 
+/// Builds the params of a `Swapped` event, with the input and output given as (denom, amount).
+fn swapped_params(pool: &str, address: &str, input: (&str, &str), output: (&str, &str)) -> Value {
+  let coins = |(denom, amount): (&str, &str)| json!({
+    "constructor": "Coins",
+    "arguments": [{ "constructor": denom, "arguments": [] }, amount],
+  });
+  json!([
+    { "vname": "pool", "type": "ByStr20", "value": pool },
+    { "vname": "address", "type": "ByStr20", "value": address },
+    { "vname": "input", "type": "Coins", "value": coins(input) },
+    { "vname": "output", "type": "Coins", "value": coins(output) },
+  ])
+}
+
+/// Finds the value of an event param by its name, as params
+/// may be reordered between versions of a contract.
+fn event_param<'a>(params: &'a Value, name: &str) -> Option<&'a Value> {
+  params.as_array()?
+    .iter()
+    .find(|param| param["vname"] == name)
+    .map(|param| &param["value"])
+}
+
+/// Parses a block timestamp, given by the node in microseconds.
+fn parse_block_timestamp(timestamp: &str) -> Option<NaiveDateTime> {
+  let timestamp = timestamp.parse::<i64>().ok()?;
+  let timestamp_seconds = timestamp / 1000;
+  NaiveDateTime::from_timestamp_opt(timestamp_seconds / 1000, (timestamp_seconds % 1000).try_into().ok()?)
+}
+
+/// Gets an event param that is a string, or a string nested within it at the given json pointer.
+fn string_param<'a>(params: &'a Value, name: &str, pointer: &str) -> Result<&'a str, PersistError> {
+  event_param(params, name)
+    .and_then(|value| value.pointer(pointer))
+    .and_then(Value::as_str)
+    .ok_or_else(|| PersistError::Malformed(format!("missing param {}{}", name, pointer)))
+}
+
+/// Parses an amount of an event.
+fn parse_amount(amount: &str) -> Result<BigDecimal, PersistError> {
+  BigDecimal::from_str(amount).map_err(|_| PersistError::Malformed(format!("invalid amount {:?}", amount)))
+}
+
+/// Converts a hex address of an event to bech32.
+fn bech32_address(address: &str) -> Result<String, PersistError> {
+  let invalid = || PersistError::Malformed(format!("invalid address {:?}", address));
+  let address_bytes = address.get(2..).and_then(|hex| hex::decode(hex).ok()).ok_or_else(invalid)?;
+  encode("zil", address_bytes.to_base32()).map_err(|_| invalid())
+}
+
 fn persist_mint_event(conn: &PgConnection, _block: &models::NewBlockSync, tx_result: &TxResult, chain_event: &ChainEvent) -> PersistResult {
   let name = chain_event.name.as_str();
   if name != "Mint" {
     return Ok(false)
   }
 
-  let pool = chain_event.params.pointer("/0/value").unwrap().as_str().expect("Malformed event log!");
-  let address = chain_event.params.pointer("/1/value").unwrap().as_str().expect("Malformed event log!");
-  let amount = chain_event.params.pointer("/2/value").unwrap().as_str().expect("Malformed event log!");
+  let pool = string_param(&chain_event.params, "pool", "")?;
+  let address = string_param(&chain_event.params, "address", "")?;
+  let amount = string_param(&chain_event.params, "amount", "")?;
 
   let tx_events = tx_result.receipt.events();
-  let transfer_event = tx_events.iter().find(|&event| event._eventname.as_str() == "TransferFromSuccess").unwrap();
-  let token_amount = transfer_event.params.pointer("/3/value").unwrap().as_str().expect("Malformed event log!");
+  let transfer_event = tx_events.iter().find(|&event| event._eventname.as_str() == "TransferFromSuccess")
+    .ok_or_else(|| PersistError::Malformed(String::from("missing TransferFromSuccess event")))?;
+  let token_amount = string_param(&transfer_event.params, "amount", "")?;
   let zil_amount = tx_result.amount.as_str();
 
-  let address_bytes = hex::decode(&address[2..]).unwrap().to_base32();
-  let initiator_address_bech32 = encode("zil", &address_bytes).expect("invalid sender address");
-
-  let pool_address_bytes = hex::decode(&pool[2..]).unwrap().to_base32();
-  let pool_address_bech32 = encode("zil", &pool_address_bytes).expect("invalid pool address");
+  let initiator_address_bech32 = bech32_address(address)?;
+  let pool_address_bech32 = bech32_address(pool)?;
 
   let add_liquidity = models::NewLiquidityChange {
     transaction_hash: &chain_event.tx_hash,
@@ -430,13 +1141,18 @@ fn persist_mint_event(conn: &PgConnection, _block: &models::NewBlockSync, tx_res
     block_timestamp: &chain_event.block_timestamp,
     initiator_address: &initiator_address_bech32,
     token_address: &pool_address_bech32,
-    change_amount: &BigDecimal::from_str(amount).unwrap(),
-    token_amount: &BigDecimal::from_str(token_amount).unwrap(),
-    zil_amount: &BigDecimal::from_str(zil_amount).unwrap(),
+    change_amount: &parse_amount(amount)?,
+    token_amount: &parse_amount(token_amount)?,
+    zil_amount: &parse_amount(zil_amount)?,
+    success: &tx_result.receipt.success,
   };
 
   debug!("Inserting: {:?}", add_liquidity);
-  db::insert_liquidity_change(add_liquidity, &conn).map(|_| true)
+  let inserted = db::insert_liquidity_change(add_liquidity, &conn)?;
+  if !inserted {
+    debug!("Skipped duplicate event: {} {}", chain_event.tx_hash, chain_event.event_index);
+  }
+  Ok(inserted)
 }
 
 fn persist_burn_event(conn: &PgConnection, _block: &models::NewBlockSync, tx_result: &TxResult, chain_event: &ChainEvent) -> PersistResult {
@@ -445,22 +1161,21 @@ fn persist_burn_event(conn: &PgConnection, _block: &models::NewBlockSync, tx_res
     return Ok(false)
   }
 
-  let pool = chain_event.params.pointer("/0/value").unwrap().as_str().expect("Malformed event log!");
-  let address = chain_event.params.pointer("/1/value").unwrap().as_str().expect("Malformed event log!");
-  let amount = chain_event.params.pointer("/2/value").unwrap().as_str().expect("Malformed event log!");
+  let pool = string_param(&chain_event.params, "pool", "")?;
+  let address = string_param(&chain_event.params, "address", "")?;
+  let amount = string_param(&chain_event.params, "amount", "")?;
 
   let tx_events = tx_result.receipt.events();
-  let transfer_event = tx_events.iter().find(|&event| event._eventname.as_str() == "TransferSuccess").unwrap();
-  let token_amount = transfer_event.params.pointer("/2/value").unwrap().as_str().expect("Malformed event log!");
+  let transfer_event = tx_events.iter().find(|&event| event._eventname.as_str() == "TransferSuccess")
+    .ok_or_else(|| PersistError::Malformed(String::from("missing TransferSuccess event")))?;
+  let token_amount = string_param(&transfer_event.params, "amount", "")?;
   let tx_transitions = tx_result.receipt.transitions();
-  let zil_transition = tx_transitions.iter().find(|&transition| transition.msg._tag.as_str() == "AddFunds").unwrap();
+  let zil_transition = tx_transitions.iter().find(|&transition| transition.msg._tag.as_str() == "AddFunds")
+    .ok_or_else(|| PersistError::Malformed(String::from("missing AddFunds transition")))?;
   let zil_amount = zil_transition.msg._amount.as_str();
 
-  let address_bytes = hex::decode(&address[2..]).unwrap().to_base32();
-  let initiator_address_bech32 = encode("zil", &address_bytes).expect("invalid sender address");
-
-  let pool_address_bytes = hex::decode(&pool[2..]).unwrap().to_base32();
-  let pool_address_bech32 = encode("zil", &pool_address_bytes).expect("invalid pool address");
+  let initiator_address_bech32 = bech32_address(address)?;
+  let pool_address_bech32 = bech32_address(pool)?;
 
   let remove_liquidity = models::NewLiquidityChange {
     transaction_hash: &chain_event.tx_hash,
@@ -469,53 +1184,56 @@ fn persist_burn_event(conn: &PgConnection, _block: &models::NewBlockSync, tx_res
     block_timestamp: &chain_event.block_timestamp,
     initiator_address: &initiator_address_bech32,
     token_address: &pool_address_bech32,
-    change_amount: &BigDecimal::from_str(amount).unwrap().neg(),
-    token_amount: &BigDecimal::from_str(token_amount).unwrap(),
-    zil_amount: &BigDecimal::from_str(zil_amount).unwrap(),
+    change_amount: &parse_amount(amount)?.neg(),
+    token_amount: &parse_amount(token_amount)?,
+    zil_amount: &parse_amount(zil_amount)?,
+    success: &tx_result.receipt.success,
   };
 
   debug!("Inserting: {:?}", remove_liquidity);
-  db::insert_liquidity_change(remove_liquidity, &conn).map(|_| true)
+  let inserted = db::insert_liquidity_change(remove_liquidity, &conn)?;
+  if !inserted {
+    debug!("Skipped duplicate event: {} {}", chain_event.tx_hash, chain_event.event_index);
+  }
+  Ok(inserted)
 }
 
-fn persist_swap_event(conn: &PgConnection, _block: &models::NewBlockSync, _tx_result: &TxResult, chain_event: &ChainEvent) -> PersistResult {
+fn persist_swap_event(conn: &PgConnection, _block: &models::NewBlockSync, tx_result: &TxResult, chain_event: &ChainEvent) -> PersistResult {
   let name = chain_event.name.as_str();
   if name != "Swapped" {
     return Ok(false)
   }
 
-  let address = chain_event.params.pointer("/1/value").unwrap().as_str().expect("Malformed event log!");
-  let pool = chain_event.params.pointer("/0/value").unwrap().as_str().expect("Malformed event log!");
-  let input_amount = chain_event.params.pointer("/2/value/arguments/1").unwrap().as_str().expect("Malformed event log!");
-  let output_amount = chain_event.params.pointer("/3/value/arguments/1").unwrap().as_str().expect("Malformed event log!");
-  let input_name = chain_event.params.pointer("/2/value/arguments/0/constructor").unwrap().as_str().expect("Malformed event log!");
-  let input_denom = input_name.split(".").last().expect("Malformed event log!");
+  let address = string_param(&chain_event.params, "address", "")?;
+  let pool = string_param(&chain_event.params, "pool", "")?;
+  let input_amount = string_param(&chain_event.params, "input", "/arguments/1")?;
+  let output_amount = string_param(&chain_event.params, "output", "/arguments/1")?;
+  let input_name = string_param(&chain_event.params, "input", "/arguments/0/constructor")?;
+  let input_denom = input_name.rsplit('.').next().unwrap_or_default();
 
-  let address_bytes = hex::decode(&address[2..]).unwrap().to_base32();
-  let initiator_address_bech32 = encode("zil", &address_bytes).expect("invalid sender address");
-
-  let pool_address_bytes = hex::decode(&pool[2..]).unwrap().to_base32();
-  let pool_address_bech32 = encode("zil", &pool_address_bytes).expect("invalid pool address");
+  let initiator_address_bech32 = bech32_address(address)?;
+  let pool_address_bech32 = bech32_address(pool)?;
 
   let token_amount;
   let zil_amount;
   let is_sending_zil;
   match input_denom {
     "Token" => {
-      token_amount = BigDecimal::from_str(input_amount).unwrap();
-      zil_amount = BigDecimal::from_str(output_amount).unwrap();
+      token_amount = parse_amount(input_amount)?;
+      zil_amount = parse_amount(output_amount)?;
       is_sending_zil = false;
     },
     "Zil" => {
-      zil_amount = BigDecimal::from_str(input_amount).unwrap();
-      token_amount = BigDecimal::from_str(output_amount).unwrap();
+      zil_amount = parse_amount(input_amount)?;
+      token_amount = parse_amount(output_amount)?;
       is_sending_zil = true;
     }
     _ => {
-      panic!("Malformed input denom!");
+      return Err(PersistError::Malformed(format!("invalid input denom {:?}", input_name)))
     }
   }
 
+  let fee = tx_result.fee();
   let new_swap = models::NewSwap {
     transaction_hash: &chain_event.tx_hash,
     event_sequence: &chain_event.event_index,
@@ -526,25 +1244,64 @@ fn persist_swap_event(conn: &PgConnection, _block: &models::NewBlockSync, _tx_re
     token_amount: &token_amount,
     zil_amount: &zil_amount,
     is_sending_zil: &is_sending_zil,
+    fee: fee.as_ref(),
+    success: &tx_result.receipt.success,
   };
 
   debug!("Inserting: {:?}", new_swap);
-  db::insert_swap(new_swap, &conn).map(|_| true)
+  let inserted = db::insert_swap(new_swap, &conn)?;
+  if !inserted {
+    debug!("Skipped duplicate event: {} {}", chain_event.tx_hash, chain_event.event_index);
+  }
+  Ok(inserted)
 }
 
-fn persist_claim_event(conn: &PgConnection, _block: &models::NewBlockSync, _tx_result: &TxResult, chain_event: &ChainEvent) -> PersistResult {
+fn persist_sync_event(conn: &PgConnection, _block: &models::NewBlockSync, tx_result: &TxResult, chain_event: &ChainEvent) -> PersistResult {
+  let name = chain_event.name.as_str();
+  // the reserves of a failed transaction were never applied
+  if name != "PoolSynced" || !tx_result.receipt.success {
+    return Ok(false)
+  }
+
+  let pool = string_param(&chain_event.params, "pool", "")?;
+  let zil_reserve = string_param(&chain_event.params, "zil_reserve", "")?;
+  let token_reserve = string_param(&chain_event.params, "token_reserve", "")?;
+
+  let pool_address_bech32 = bech32_address(pool)?;
+
+  let new_reserve = models::NewReserve {
+    transaction_hash: &chain_event.tx_hash,
+    event_sequence: &chain_event.event_index,
+    block_height: &chain_event.block_height,
+    block_timestamp: &chain_event.block_timestamp,
+    token_address: &pool_address_bech32,
+    zil_reserve: &parse_amount(zil_reserve)?,
+    token_reserve: &parse_amount(token_reserve)?,
+  };
+
+  debug!("Inserting: {:?}", new_reserve);
+  let inserted = db::insert_reserve(new_reserve, conn)?;
+  if !inserted {
+    debug!("Skipped duplicate event: {} {}", chain_event.tx_hash, chain_event.event_index);
+  }
+  Ok(inserted)
+}
+
+fn persist_claim_event(conn: &PgConnection, _block: &models::NewBlockSync, tx_result: &TxResult, chain_event: &ChainEvent) -> PersistResult {
   let name = chain_event.name.as_str();
   if name != "Claimed" {
     return Ok(false)
   }
 
-  let epoch_number = chain_event.params.pointer("/0/value").unwrap().as_str().expect("Malformed event log!");
-  let recipient_address = chain_event.params.pointer("/1/value/arguments/0").unwrap().as_str().expect("Malformed event log!");
-  let amount = chain_event.params.pointer("/1/value/arguments/1").unwrap().as_str().expect("Malformed event log!");
+  let epoch_number = string_param(&chain_event.params, "epoch_number", "")?;
+  let recipient_address = string_param(&chain_event.params, "data", "/arguments/0")?;
+  let amount = string_param(&chain_event.params, "data", "/arguments/1")?;
 
-  let address_bytes = hex::decode(&recipient_address[2..]).unwrap().to_base32();
-  let initiator_address = encode("zil", &address_bytes).expect("invalid sender address");
+  let initiator_address = bech32_address(recipient_address)?;
+  let epoch_number = epoch_number.parse::<i32>()
+    .map_err(|_| PersistError::Malformed(format!("invalid epoch number {:?}", epoch_number)))?;
 
+  let fee = tx_result.fee();
   let new_claim = models::NewClaim {
     transaction_hash: &chain_event.tx_hash,
     event_sequence: &chain_event.event_index,
@@ -552,10 +1309,292 @@ fn persist_claim_event(conn: &PgConnection, _block: &models::NewBlockSync, _tx_r
     block_timestamp: &chain_event.block_timestamp,
     initiator_address: &initiator_address,
     distributor_address: &chain_event.contract_address,
-    epoch_number: &epoch_number.parse::<i32>().expect("Malformed event log"),
-    amount: &BigDecimal::from_str(amount).unwrap(),
+    epoch_number: &epoch_number,
+    amount: &parse_amount(amount)?,
+    fee: fee.as_ref(),
+    success: &tx_result.receipt.success,
   };
 
   debug!("Inserting: {:?}", new_claim);
-  db::insert_claim(new_claim, &conn).map(|_| true)
+  let inserted = db::insert_claim(new_claim, &conn)?;
+  if !inserted {
+    debug!("Skipped duplicate event: {} {}", chain_event.tx_hash, chain_event.event_index);
+  }
+  Ok(inserted)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_utils::*;
+  use diesel::prelude::*;
+  use serde_json::json;
+
+  const POOL: &str = "0xa845c1034cd077bd8d32be0447239c7e4be6cb21";
+  const SENDER_PUB_KEY: &str = "0x0205273e54f262f8717a687250591dcfb5755b8ce4e3bd340c7abefd0de1276574";
+
+  /// An actor for the test config, with a pool, a node and a coordinator that are never used.
+  fn test_actor() -> EventFetchActor {
+    let config = worker_config();
+    let db_pool = Pool::builder().build_unchecked(ConnectionManager::new("postgres://127.0.0.1/unused"));
+    let cache = Pool::builder().build_unchecked(redis::Client::open("redis://127.0.0.1/").unwrap());
+    let zil_client = ZilliqaClient::new(&config.rpc_urls, config.rpc_timeout);
+    let (coordinator, _) = actix::dev::channel::channel::<Coordinator>(0);
+    EventFetchActor::new(config, db_pool, cache, zil_client, Addr::new(coordinator))
+  }
+
+  /// A block as returned by the node, with its timestamp in microseconds.
+  fn test_block(block_height: i32, num_txs: i32) -> BlockResult {
+    serde_json::from_value(json!({
+      "header": {
+        "BlockNum": block_height.to_string(),
+        "NumTxns": num_txs,
+        "Timestamp": (block_timestamp(block_height).timestamp() * 1_000_000).to_string(),
+      },
+      "body": { "BlockHash": hex::encode(rand::random::<[u8; 32]>()) },
+    })).unwrap()
+  }
+
+  /// A successful transaction with the given event logs, keyed by its hash without the `0x` prefix.
+  fn test_tx(event_logs: Value) -> (String, TxResult) {
+    let tx_hash = random_tx_hash().trim_start_matches("0x").to_owned();
+    let tx_result = serde_json::from_value(json!({
+      "ID": tx_hash,
+      "amount": "0",
+      "nonce": "1",
+      "receipt": { "success": true, "event_logs": event_logs, "cumulative_gas": "600" },
+      "senderPubKey": SENDER_PUB_KEY,
+      "gasLimit": "10000",
+      "gasPrice": "2000000000",
+    })).unwrap();
+    (tx_hash, tx_result)
+  }
+
+  /// A `Swapped` event emitted by the test contract.
+  fn swapped_event(input: (&str, &str), output: (&str, &str)) -> Value {
+    json!({
+      "_eventname": "Swapped",
+      "address": worker_config().contract_hash,
+      "params": swapped_params(POOL, "0x0d21c1901a06abee40d8177f95171c8c63abdc31", input, output),
+    })
+  }
+
+  fn load_swaps(conn: &PgConnection, tx_hash: &str) -> Vec<models::Swap> {
+    use crate::schema::swaps::dsl::*;
+    swaps.filter(transaction_hash.eq(format!("0x{}", tx_hash))).load(conn).unwrap()
+  }
+
+  #[test]
+  #[ignore]
+  fn test_status() {
+    let conn = test_conn();
+    let height = random_block_height();
+    seed_block_sync(&conn, height, "0x00");
+
+    let status = worker_config().status(true, db::last_sync_height(&conn).unwrap());
+    let status = serde_json::to_value(status).unwrap();
+    let mut keys: Vec<&str> = status.as_object().unwrap().keys().map(String::as_str).collect();
+    keys.sort_unstable();
+    assert_eq!(keys, vec![
+      "batch_size", "chain_height", "confirmation_depth", "contract_address", "distributor_addresses",
+      "fetch_errors", "jobs_in_flight", "last_sync_height", "min_sync_height", "network", "poll_interval",
+      "running", "sync_lag", "worker_threads",
+    ]);
+    assert_eq!(status["running"], true);
+    assert_eq!(status["network"], "mainnet");
+    assert_eq!(status["contract_address"], "0x459cb2d3baf7e61cfbd5fe362f289ae92b2babb0");
+    assert_eq!(status["last_sync_height"], height);
+    assert_eq!(status["poll_interval"], POLL_INTERVAL);
+  }
+
+  #[test]
+  #[ignore]
+  fn test_sync_block_twice() {
+    let conn = test_conn();
+    let actor = test_actor();
+    let block_height = random_block_height();
+    let block = test_block(block_height, 1);
+    let (tx_hash, tx_result) = test_tx(json!([swapped_event(("Zil", "1000"), ("Token", "20"))]));
+
+    actor.sync_block(&conn, &block, vec![(tx_hash.clone(), tx_result.clone())], 0, 0).unwrap();
+    assert_eq!(load_swaps(&conn, &tx_hash).len(), 1);
+
+    actor.sync_block(&conn, &block, vec![(tx_hash.clone(), tx_result)], 0, 0).unwrap();
+    assert_eq!(load_swaps(&conn, &tx_hash).len(), 1);
+    assert_eq!(db::get_block_syncs(&conn, block_height, block_height).unwrap().len(), 1);
+  }
+
+  #[test]
+  #[ignore]
+  fn test_malformed_event_is_recorded_as_failed() {
+    use crate::schema::failed_events::dsl::*;
+
+    let conn = test_conn();
+    let actor = test_actor();
+    let block = test_block(random_block_height(), 1);
+    let mut event = swapped_event(("Zil", "1000"), ("Token", "20"));
+    event["params"].as_array_mut().unwrap().retain(|param| param["vname"] != "output");
+    let (tx_hash, tx_result) = test_tx(json!([event]));
+
+    // malformed events are skipped on the first attempt, as retrying cannot help
+    actor.sync_block(&conn, &block, vec![(tx_hash.clone(), tx_result)], 0, 0).unwrap();
+    assert_eq!(load_swaps(&conn, &tx_hash).len(), 0);
+
+    let failed: Vec<models::FailedEvent> = failed_events
+      .filter(transaction_hash.eq(format!("0x{}", tx_hash)))
+      .load(&conn)
+      .unwrap();
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].event_name, "Swapped");
+    assert_eq!(failed[0].error, "malformed event: missing param output/arguments/1");
+  }
+
+  #[test]
+  fn test_retry_counts_persist_failures_separately() {
+    let msg = Fetch::process_block(1);
+    let msg = NextFetch::retry(&msg, &utils::FetchError::MalformedBlock(String::from("invalid timestamp"))).get_next().unwrap();
+    assert_eq!((msg.attempt, msg.persist_failures), (1, 0));
+
+    let msg = NextFetch::retry(&msg, &utils::FetchError::Persist(diesel::result::Error::RollbackTransaction)).get_next().unwrap();
+    assert_eq!((msg.attempt, msg.persist_failures), (1, 1));
+  }
+
+  #[test]
+  #[ignore]
+  fn test_swap_fee_is_stored() {
+    let conn = test_conn();
+    let actor = test_actor();
+    let block = test_block(random_block_height(), 1);
+    let (tx_hash, tx_result) = test_tx(json!([swapped_event(("Token", "20"), ("Zil", "1000"))]));
+    // 600 gas used at a gas price of 2000000000 Qa
+    assert_eq!(tx_result.fee(), Some(BigDecimal::from(1_200_000_000_000i64)));
+
+    actor.sync_block(&conn, &block, vec![(tx_hash.clone(), tx_result)], 0, 0).unwrap();
+    let swaps = load_swaps(&conn, &tx_hash);
+    assert_eq!(swaps.len(), 1);
+    assert_eq!(swaps[0].fee, Some(BigDecimal::from(1_200_000_000_000i64)));
+    assert_eq!(swaps[0].zil_amount, BigDecimal::from(1000));
+    assert!(!swaps[0].is_sending_zil);
+  }
+
+  #[test]
+  #[ignore]
+  fn test_swap_success_is_stored() {
+    let conn = test_conn();
+    let actor = test_actor();
+    let block = test_block(random_block_height(), 2);
+    let (tx_hash, tx_result) = test_tx(json!([swapped_event(("Zil", "1000"), ("Token", "20"))]));
+    let (failed_tx_hash, mut failed_tx_result) = test_tx(json!([swapped_event(("Zil", "1000"), ("Token", "20"))]));
+    failed_tx_result.receipt.success = false;
+
+    actor.sync_block(&conn, &block, vec![(tx_hash.clone(), tx_result), (failed_tx_hash.clone(), failed_tx_result)], 0, 0).unwrap();
+    let swaps = load_swaps(&conn, &tx_hash);
+    assert_eq!(swaps.len(), 1);
+    assert!(swaps[0].success);
+    let failed_swaps = load_swaps(&conn, &failed_tx_hash);
+    assert_eq!(failed_swaps.len(), 1);
+    assert!(!failed_swaps[0].success);
+
+    let pool = bech32_address(POOL).unwrap();
+    let tx_hashes = |is_success: bool| {
+      let swaps = db::get_swaps(&conn, None, None, Some(&pool), None, None, Some(&is_success), db::SwapExclusions::default(), db::BlockRange::default(), None).unwrap();
+      serde_json::to_value(swaps).unwrap()["records"].as_array().unwrap().iter()
+        .map(|swap| swap["transaction_hash"].as_str().unwrap().to_owned())
+        .collect::<Vec<String>>()
+    };
+    assert_eq!(tx_hashes(true), vec![format!("0x{}", tx_hash)]);
+    assert_eq!(tx_hashes(false), vec![format!("0x{}", failed_tx_hash)]);
+  }
+
+  #[test]
+  #[ignore]
+  fn test_pool_synced_event_is_stored() {
+    use crate::schema::reserves::dsl::*;
+
+    let conn = test_conn();
+    let actor = test_actor();
+    let block = test_block(random_block_height(), 2);
+    let synced_event = |zil: &str, token: &str| json!({
+      "_eventname": "PoolSynced",
+      "address": worker_config().contract_hash,
+      "params": [
+        { "vname": "pool", "type": "ByStr20", "value": POOL },
+        { "vname": "zil_reserve", "type": "Uint128", "value": zil },
+        { "vname": "token_reserve", "type": "Uint128", "value": token },
+      ],
+    });
+    let (tx_hash, tx_result) = test_tx(json!([synced_event("1000", "20")]));
+    let (failed_tx_hash, mut failed_tx_result) = test_tx(json!([synced_event("2000", "10")]));
+    failed_tx_result.receipt.success = false;
+
+    actor.sync_block(&conn, &block, vec![(tx_hash.clone(), tx_result), (failed_tx_hash.clone(), failed_tx_result)], 0, 0).unwrap();
+
+    // the reserves of the failed transaction are left out
+    let stored: Vec<models::Reserve> = reserves
+      .filter(transaction_hash.eq_any(vec![format!("0x{}", tx_hash), format!("0x{}", failed_tx_hash)]))
+      .load(&conn)
+      .unwrap();
+    assert_eq!(stored.len(), 1);
+    assert_eq!(stored[0].token_address, bech32_address(POOL).unwrap());
+    assert_eq!(stored[0].zil_reserve, BigDecimal::from(1000));
+    assert_eq!(stored[0].token_reserve, BigDecimal::from(20));
+  }
+
+  #[test]
+  fn test_parse_block_timestamp() {
+    assert_eq!(parse_block_timestamp("1600000000000000"), Some(NaiveDateTime::from_timestamp(1_600_000_000, 0)));
+    assert_eq!(parse_block_timestamp(""), None);
+    assert_eq!(parse_block_timestamp("not a timestamp"), None);
+  }
+
+  #[test]
+  #[ignore]
+  fn test_malformed_block_timestamp() {
+    let conn = test_conn();
+    let actor = test_actor();
+    let block_height = random_block_height();
+    seed_block_sync(&conn, block_height - 1, EMPTY_BLOCK_HASH);
+    let mut block = test_block(block_height, 0);
+    block.header.timestamp = String::from("");
+
+    // the block is retried before falling back on the previous block's timestamp
+    match actor.sync_block(&conn, &block, vec![], 0, 0) {
+      Err(utils::FetchError::MalformedBlock(_)) => {},
+      result => panic!("expected a malformed block, got {:?}", result.map_err(|e| e.to_string())),
+    }
+    assert!(db::get_block_sync(&conn, block_height).unwrap().is_none());
+
+    actor.sync_block(&conn, &block, vec![], MAX_BLOCK_ATTEMPTS - 1, 0).unwrap();
+    let block_sync = db::get_block_sync(&conn, block_height).unwrap().unwrap();
+    assert_eq!(block_sync.block_timestamp, block_timestamp(block_height - 1));
+  }
+
+  #[test]
+  fn test_event_param_by_name() {
+    let mut params = swapped_params(POOL, "0x0d21c1901a06abee40d8177f95171c8c63abdc31", ("Zil", "1000"), ("Token", "20"));
+    params.as_array_mut().unwrap().reverse();
+    assert_eq!(event_param(&params, "pool"), Some(&json!(POOL)));
+    assert_eq!(string_param(&params, "output", "/arguments/1").unwrap(), "20");
+    assert_eq!(string_param(&params, "input", "/arguments/0/constructor").unwrap(), "Zil");
+    assert_eq!(event_param(&params, "recipient"), None);
+  }
+
+  #[test]
+  #[ignore]
+  fn test_reordered_params() {
+    let conn = test_conn();
+    let actor = test_actor();
+    let block = test_block(random_block_height(), 1);
+    let mut event = swapped_event(("Zil", "1000"), ("Token", "20"));
+    event["params"].as_array_mut().unwrap().reverse();
+    let (tx_hash, tx_result) = test_tx(json!([event]));
+
+    actor.sync_block(&conn, &block, vec![(tx_hash.clone(), tx_result)], 0, 0).unwrap();
+    let swaps = load_swaps(&conn, &tx_hash);
+    assert_eq!(swaps.len(), 1);
+    assert_eq!(swaps[0].token_address, bech32_address(POOL).unwrap());
+    assert_eq!(swaps[0].zil_amount, BigDecimal::from(1000));
+    assert_eq!(swaps[0].token_amount, BigDecimal::from(20));
+    assert!(swaps[0].is_sending_zil);
+  }
 }